@@ -0,0 +1,198 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use libc::{timespec, clock_gettime, CLOCK_MONOTONIC};
+
+use base::config;
+use base::node;
+
+
+/// Source of time for stamps and periodic loops of the current context, see `node::Context`.
+///
+/// The process-wide context uses the clock chosen by the config, `base::harness` uses `Manual`.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Duration;
+    /// Blocks until `now()` reaches the deadline.
+    fn sleep_until(&self, deadline: Duration);
+    /// Converts a `CLOCK_MONOTONIC` stamp (e.g. of V4L2 buffers) to the time of the clock.
+    fn from_monotonic(&self, stamp: Duration) -> Duration;
+}
+
+/// `CLOCK_MONOTONIC`, the default one.
+pub struct Monotonic;
+
+impl Clock for Monotonic {
+    fn now(&self) -> Duration {
+        monotonic()
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        let now = self.now();
+
+        if deadline > now {
+            thread::sleep(deadline - now);
+        }
+    }
+
+    fn from_monotonic(&self, stamp: Duration) -> Duration {
+        stamp
+    }
+}
+
+/// `CLOCK_MONOTONIC` running `scale` times faster since the creation, e.g. to replay recordings
+/// faster than real time along with periodic nodes.
+pub struct Scaled {
+    origin: Duration,
+    scale: f64
+}
+
+impl Scaled {
+    pub fn new(scale: f64) -> Scaled {
+        assert!(scale > 0.);
+        Scaled { origin: monotonic(), scale: scale }
+    }
+
+    fn scale(&self, duration: Duration, scale: f64) -> Duration {
+        let nanos = (nanos(duration) as f64 * scale) as u64;
+        Duration::new(nanos / 1000000000, (nanos % 1000000000) as u32)
+    }
+}
+
+impl Clock for Scaled {
+    fn now(&self) -> Duration {
+        self.from_monotonic(monotonic())
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        let now = self.now();
+
+        if deadline > now {
+            thread::sleep(self.scale(deadline - now, 1. / self.scale));
+        }
+    }
+
+    fn from_monotonic(&self, stamp: Duration) -> Duration {
+        if stamp > self.origin {
+            self.origin + self.scale(stamp - self.origin, self.scale)
+        } else {
+            stamp
+        }
+    }
+}
+
+/// Time, which moves only by `advance`, for tests.
+pub struct Manual {
+    state: Mutex<ManualState>,
+    cond: Condvar
+}
+
+struct ManualState {
+    now: Duration,
+    released: bool,
+    /// Deadlines of blocked sleepers.
+    sleeping: Vec<Duration>
+}
+
+impl Manual {
+    pub fn new(start: Duration) -> Manual {
+        Manual {
+            state: Mutex::new(ManualState { now: start, released: false, sleeping: Vec::new() }),
+            cond: Condvar::new()
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.state.lock().unwrap().now += duration;
+        self.cond.notify_all();
+    }
+
+    /// Waits until `count` threads sleep till deadlines after now, i.e. they are done with
+    /// everything due by now. Returns false if it takes longer than `timeout` of real time.
+    pub fn wait_sleeping(&self, count: usize, timeout: Duration) -> bool {
+        let limit = monotonic() + timeout;
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if state.sleeping.iter().filter(|&&deadline| deadline > state.now).count() >= count {
+                return true;
+            }
+
+            let now = monotonic();
+
+            if now >= limit {
+                return false;
+            }
+
+            state = self.cond.wait_timeout(state, limit - now).unwrap().0;
+        }
+    }
+
+    /// Wakes all sleepers and stops blocking, so loops can notice the shutdown.
+    pub fn release(&self) {
+        self.state.lock().unwrap().released = true;
+        self.cond.notify_all();
+    }
+}
+
+impl Clock for Manual {
+    fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.now >= deadline || state.released {
+            return;
+        }
+
+        // Let `wait_sleeping` know.
+        state.sleeping.push(deadline);
+        self.cond.notify_all();
+
+        while state.now < deadline && !state.released {
+            state = self.cond.wait(state).unwrap();
+        }
+
+        let index = state.sleeping.iter().position(|&x| x == deadline).unwrap();
+        state.sleeping.swap_remove(index);
+    }
+
+    fn from_monotonic(&self, _: Duration) -> Duration {
+        self.now()
+    }
+}
+
+/// Returns the clock chosen by the config (`clock.scale`), `Monotonic` if the config isn't set.
+///
+/// Called while creating the global context, so it must not log: the logger publishes records.
+pub fn configured() -> Arc<Clock> {
+    match config::try_get().map_or(1., |config| config.clock.scale) {
+        scale if scale == 1. => Arc::new(Monotonic),
+        scale => Arc::new(Scaled::new(scale))
+    }
+}
+
+/// Returns the current time of the context.
+pub fn now() -> Duration {
+    node::context().clock.now()
+}
+
+pub fn sleep_until(deadline: Duration) {
+    node::context().clock.sleep_until(deadline)
+}
+
+pub fn from_monotonic(stamp: Duration) -> Duration {
+    node::context().clock.from_monotonic(stamp)
+}
+
+fn monotonic() -> Duration {
+    let mut ts = timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { clock_gettime(CLOCK_MONOTONIC, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1000000000 + duration.subsec_nanos() as u64
+}
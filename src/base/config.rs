@@ -0,0 +1,880 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::path::Path;
+use std::sync::{Arc, Once, ONCE_INIT};
+
+use base::{Error, Result};
+use base::logger;
+use base::node::STACK_SIZE;
+use constants::*;
+
+
+pub const DEFAULT_PATH: &'static str = "hodok.toml";
+pub const DEFAULT_PROFILE: &'static str = "full";
+
+const USAGE: &'static str = "usage: hodok [--config FILE] [--profile NAME] [--nodes NODE,...] \
+                             [--set KEY=VALUE]...";
+
+pub struct Config {
+    /// Explicit list of nodes, has priority over the profile.
+    pub nodes: Option<Vec<String>>,
+    pub profile: String,
+    /// Profiles defined in the `[profiles]` section in addition to the builtin ones.
+    pub profiles: HashMap<String, Vec<String>>,
+
+    pub ahrs: AhrsConfig,
+    pub server: ServerConfig,
+    pub video: VideoConfig,
+    pub sysinfo: SysInfoConfig,
+    pub recorder: RecorderConfig,
+    pub player: PlayerConfig,
+    pub supervisor: SupervisorConfig,
+    pub shutdown: ShutdownConfig,
+    pub params: ParamsConfig,
+    pub watchdog: WatchdogConfig,
+    pub clock: ClockConfig,
+    pub log: LogConfig,
+    pub crash: CrashConfig,
+    /// Scheduling of nodes from `[sched.<node>]` sections.
+    pub sched: HashMap<String, SchedConfig>
+}
+
+pub struct AhrsConfig {
+    pub device: String,
+    pub rate: f32,          // [Hz]
+    pub accel_range: f32,   // [g]
+    pub magn_range: f32,    // [Gauss]
+    pub gyro_range: f32,    // [°/s]
+    /// Gain of the Madgwick filter.
+    pub beta: f32
+}
+
+pub struct ServerConfig {
+    pub port: u16,
+    pub video_queue: usize, // [frames]
+    /// Maximum rate of attitude updates sent to clients.
+    pub attitude_rate: f32, // [Hz]
+    /// Recent log records sent to new clients.
+    pub log_history: usize  // [records]
+}
+
+pub struct VideoConfig {
+    pub device: String,
+    pub fps: u32,
+    pub resolution: (u32, u32),
+    pub gof_size: u32,
+    pub hflip: bool,
+    pub vflip: bool
+}
+
+pub struct SysInfoConfig {
+    pub rate: f32           // [Hz]
+}
+
+pub struct RecorderConfig {
+    pub path: String,
+    /// Names of recorded kinds, see `nodes::recorder::KINDS`.
+    pub kinds: Vec<String>,
+    /// Topics of kinds from `[recorder.topics]`, only the default one for missing kinds.
+    pub topics: HashMap<String, Vec<String>>,
+    pub queue: usize        // [messages]
+}
+
+pub struct PlayerConfig {
+    pub path: String,
+    pub repeat: bool
+}
+
+pub struct SupervisorConfig {
+    pub max_failures: u32,
+    pub backoff: u64,       // [ms]
+    pub max_backoff: u64    // [ms]
+}
+
+pub struct ShutdownConfig {
+    pub timeout: u64        // [ms]
+}
+
+/// Scheduling of the node's thread, see `base::sched`.
+pub struct SchedConfig {
+    pub cpus: Option<Vec<usize>>,
+    pub nice: Option<i32>,
+    /// SCHED_FIFO priority, has priority over the nice value.
+    pub priority: Option<u32>,
+    pub stack_size: usize   // [B]
+}
+
+impl Default for SchedConfig {
+    fn default() -> SchedConfig {
+        SchedConfig { cpus: None, nice: None, priority: None, stack_size: STACK_SIZE }
+    }
+}
+
+pub struct ClockConfig {
+    /// Speed of the clock relative to real time, see `base::clock`.
+    pub scale: f64
+}
+
+pub struct WatchdogConfig {
+    pub rate: f32,          // [Hz]
+    /// Silence to raise a warning.
+    pub warn: f32,          // [periods]
+    /// Silence to raise an error.
+    pub error: f32          // [periods]
+}
+
+pub struct LogConfig {
+    /// Levels of nodes like `info,ahrs=trace`, see `base::logger`.
+    pub levels: String,
+    /// File to write logs in addition to the console.
+    pub path: Option<String>,
+    /// Size to rotate the file.
+    pub max_size: u64,      // [KiB]
+    /// Number of rotated files to keep.
+    pub keep: u32
+}
+
+pub struct CrashConfig {
+    /// Directory of crash reports, see `base::crash`.
+    pub path: String
+}
+
+pub struct ParamsConfig {
+    /// File of changed parameters, see `base::params`.
+    pub path: String
+}
+
+/// Loads the config file and applies command line arguments over it.
+///
+/// The file is optional if the path isn't provided explicitly.
+pub fn load<I: Iterator<Item=String>>(mut args: I) -> Result<Config> {
+    let mut path = None;
+    let mut profile = None;
+    let mut nodes = None;
+    let mut overrides = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} requires a value", arg));
+
+        match &arg[..] {
+            "--config" => path = Some(try!(value())),
+            "--profile" => profile = Some(try!(value())),
+            "--nodes" => nodes = Some(split_list(&try!(value()))),
+            "--set" => overrides.push(try!(value())),
+            _ => return Err(From::from(format!("unexpected argument {}\n{}", arg, USAGE)))
+        }
+    }
+
+    let mut document = match path {
+        Some(ref path) => try!(Document::open(path)),
+        None if Path::new(DEFAULT_PATH).exists() => try!(Document::open(DEFAULT_PATH)),
+        None => Document::default()
+    };
+
+    let origin = Arc::new("--set".to_string());
+
+    for item in overrides {
+        let mut split = item.splitn(2, '=');
+        let key = split.next().unwrap().trim().to_string();
+        let raw = try!(split.next().ok_or_else(|| format!("expected KEY=VALUE, got {}", item)));
+
+        // Allow unquoted strings on the command line.
+        let mut parser = Parser { rest: raw };
+        let value = match parser.value() {
+            Some(value) if parser.is_end() => value,
+            _ => Value::Str(raw.to_string())
+        };
+
+        document.insert(key, Entry { value: value, line: 0, origin: origin.clone() });
+    }
+
+    let mut config = try!(Config::read(&document));
+
+    // The command line has priority over the file.
+    if let Some(profile) = profile {
+        config.profile = profile;
+        config.nodes = None;
+    }
+
+    if nodes.is_some() {
+        config.nodes = nodes;
+    }
+
+    Ok(config)
+}
+
+impl Config {
+    fn read(document: &Document) -> Result<Config> {
+        let reader = Reader { document: document, used: RefCell::new(HashSet::new()) };
+
+        let mut profiles = HashMap::new();
+        for (key, entry) in reader.section("profiles") {
+            profiles.insert(key.to_string(), try!(entry.as_str_list()));
+        }
+
+        let mut recorder_topics = HashMap::new();
+        for (key, entry) in reader.section("recorder.topics") {
+            recorder_topics.insert(key.to_string(), try!(entry.as_str_list()));
+        }
+
+        let mut sched = HashMap::new();
+        for (key, entry) in reader.section("sched") {
+            let (node, field) = match key.rfind('.') {
+                Some(index) => (&key[..index], &key[index + 1..]),
+                None => return entry.error(format!("expected [sched.<node>], got sched.{}", key))
+            };
+
+            let config = sched.entry(node.to_string()).or_insert_with(SchedConfig::default);
+            let key = format!("sched.{}", key);
+
+            match field {
+                "cpus" => config.cpus = Some(try!(entry.as_int_list(&key, 0, 1023))
+                                                 .into_iter().map(|x| x as usize).collect()),
+                "nice" => config.nice = Some(try!(entry.as_signed(&key, -20, 19)) as i32),
+                "priority" => config.priority = Some(try!(entry.as_int(&key, 1, 99)) as u32),
+                "stack_size" => config.stack_size = try!(entry.as_int(&key, 16384, 67108864))
+                                                        as usize,
+                _ => return entry.error(format!("unknown key {}", key))
+            }
+        }
+
+        let config = Config {
+            nodes: match reader.entry("nodes") {
+                Some(entry) => Some(try!(entry.as_str_list())),
+                None => None
+            },
+            profile: try!(reader.string("profile", DEFAULT_PROFILE)),
+            profiles: profiles,
+
+            ahrs: AhrsConfig {
+                device: try!(reader.string("ahrs.device", AHRS_DEVICE)),
+                rate: try!(reader.num("ahrs.rate", AHRS_RATE as f64, 0.1, 3200.)) as f32,
+                accel_range: try!(reader.num("ahrs.accel_range", ACCEL_RANGE as f64, 2., 16.)) as f32,
+                magn_range: try!(reader.num("ahrs.magn_range", MAGN_RANGE as f64, 0.88, 8.1)) as f32,
+                gyro_range: try!(reader.num("ahrs.gyro_range", GYRO_RANGE as f64, 250., 2000.)) as f32,
+                beta: try!(reader.num("ahrs.beta", MADGWICK_BETA as f64, 0.001, 10.)) as f32
+            },
+
+            server: ServerConfig {
+                port: try!(reader.int("server.port", PORT as u64, 1, 65535)) as u16,
+                video_queue: try!(reader.int("server.video_queue", VIDEO_QUEUE_SIZE as u64, 1, 1000))
+                    as usize,
+                attitude_rate: try!(reader.num("server.attitude_rate", SERVER_ATTITUDE_RATE as f64,
+                                               0.1, 1000.)) as f32,
+                log_history: try!(reader.int("server.log_history", SERVER_LOG_HISTORY as u64,
+                                             1, 10000)) as usize
+            },
+
+            video: VideoConfig {
+                device: try!(reader.string("video.device", VIDEO_DEVICE)),
+                fps: try!(reader.int("video.fps", VIDEO_FPS as u64, 1, 120)) as u32,
+                resolution: try!(reader.pair("video.resolution", VIDEO_RESOLUTION, 16, 4096)),
+                gof_size: try!(reader.int("video.gof_size", VIDEO_GOF_SIZE as u64, 1, 10000)) as u32,
+                hflip: try!(reader.boolean("video.hflip", VIDEO_HFLIP)),
+                vflip: try!(reader.boolean("video.vflip", VIDEO_VFLIP))
+            },
+
+            sysinfo: SysInfoConfig {
+                rate: try!(reader.num("sysinfo.rate", SYSINFO_RATE as f64, 0.01, 100.)) as f32
+            },
+
+            recorder: RecorderConfig {
+                path: try!(reader.string("recorder.path", RECORDER_PATH)),
+                kinds: match reader.entry("recorder.kinds") {
+                    Some(entry) => try!(entry.as_str_list()),
+                    None => RECORDER_KINDS.iter().map(|x| x.to_string()).collect()
+                },
+                topics: recorder_topics,
+                queue: try!(reader.int("recorder.queue", RECORDER_QUEUE as u64, 1, 100000)) as usize
+            },
+
+            player: PlayerConfig {
+                path: try!(reader.string("player.path", PLAYER_PATH)),
+                repeat: try!(reader.boolean("player.repeat", PLAYER_REPEAT))
+            },
+
+            supervisor: SupervisorConfig {
+                max_failures: try!(reader.int("supervisor.max_failures",
+                                              SUPERVISOR_MAX_FAILURES as u64, 1, 1000)) as u32,
+                backoff: try!(reader.int("supervisor.backoff", SUPERVISOR_BACKOFF, 1, 3600000)),
+                max_backoff: try!(reader.int("supervisor.max_backoff",
+                                             SUPERVISOR_MAX_BACKOFF, 1, 3600000))
+            },
+
+            shutdown: ShutdownConfig {
+                timeout: try!(reader.int("shutdown.timeout", SHUTDOWN_TIMEOUT, 1, 600000))
+            },
+
+            crash: CrashConfig {
+                path: try!(reader.string("crash.path", CRASH_PATH))
+            },
+
+            params: ParamsConfig {
+                path: try!(reader.string("params.path", PARAMS_PATH))
+            },
+
+            watchdog: WatchdogConfig {
+                rate: try!(reader.num("watchdog.rate", WATCHDOG_RATE as f64, 0.1, 100.)) as f32,
+                warn: try!(reader.num("watchdog.warn", WATCHDOG_WARN as f64, 1., 1000.)) as f32,
+                error: try!(reader.num("watchdog.error", WATCHDOG_ERROR as f64, 1., 1000.)) as f32
+            },
+
+            clock: ClockConfig {
+                scale: try!(reader.num("clock.scale", CLOCK_SCALE, 0.01, 100.))
+            },
+
+            log: LogConfig {
+                levels: match reader.entry("log.levels") {
+                    Some(entry) => {
+                        let levels = try!(entry.as_str());
+
+                        if let Err(err) = logger::Levels::parse(levels) {
+                            return entry.error(format!("log.levels: {}", err));
+                        }
+
+                        levels.to_string()
+                    },
+                    None => LOG_LEVELS.to_string()
+                },
+                path: match reader.entry("log.path") {
+                    Some(entry) => Some(try!(entry.as_str()).to_string()),
+                    None => None
+                },
+                max_size: try!(reader.int("log.max_size", LOG_MAX_SIZE, 1, 1048576)),
+                keep: try!(reader.int("log.keep", LOG_KEEP as u64, 0, 100)) as u32
+            },
+
+            sched: sched
+        };
+
+        try!(reader.check_unused());
+        Ok(config)
+    }
+}
+
+/// Sets the process-wide config, must be called once before starting nodes.
+pub fn set(config: Config) {
+    let mut config = Some(config);
+
+    ONCE.call_once(|| {
+        unsafe { CONFIG = mem::transmute(Box::new(config.take().unwrap())) };
+    });
+
+    assert!(config.is_none(), "the config is already set");
+}
+
+/// Sets the default config unless any config is set, intended for tests.
+pub fn set_default() {
+    ONCE.call_once(|| {
+        let config = Config::read(&Document::default()).unwrap();
+        unsafe { CONFIG = mem::transmute(Box::new(config)) };
+    });
+}
+
+pub fn get() -> &'static Config {
+    try_get().expect("the config isn't set yet")
+}
+
+/// Returns the config if it's set, for code, which can run before `set`.
+pub fn try_get() -> Option<&'static Config> {
+    unsafe { CONFIG.as_ref() }
+}
+
+static mut CONFIG: *const Config = 0 as *const Config;
+static ONCE: Once = ONCE_INIT;
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect()
+}
+
+/// Keeps track of read keys to report unknown ones, which are likely typos.
+struct Reader<'a> {
+    document: &'a Document,
+    used: RefCell<HashSet<String>>
+}
+
+impl<'a> Reader<'a> {
+    fn entry(&self, key: &str) -> Option<&'a Entry> {
+        self.used.borrow_mut().insert(key.to_string());
+        self.document.get(key)
+    }
+
+    fn section(&self, name: &str) -> Vec<(&'a str, &'a Entry)> {
+        let entries = self.document.section(name);
+
+        for &(key, _) in &entries {
+            self.used.borrow_mut().insert(format!("{}.{}", name, key));
+        }
+
+        entries
+    }
+
+    fn string(&self, key: &str, default: &str) -> Result<String> {
+        match self.entry(key) {
+            Some(entry) => entry.as_str().map(|x| x.to_string()),
+            None => Ok(default.to_string())
+        }
+    }
+
+    fn boolean(&self, key: &str, default: bool) -> Result<bool> {
+        match self.entry(key) {
+            Some(entry) => match entry.value {
+                Value::Bool(value) => Ok(value),
+                _ => entry.error(format!("{} must be true or false, got {:?}", key, entry.value))
+            },
+            None => Ok(default)
+        }
+    }
+
+    fn num(&self, key: &str, default: f64, min: f64, max: f64) -> Result<f64> {
+        match self.entry(key) {
+            Some(entry) => entry.as_num(key, min, max),
+            None => Ok(default)
+        }
+    }
+
+    fn int(&self, key: &str, default: u64, min: u64, max: u64) -> Result<u64> {
+        match self.entry(key) {
+            Some(entry) => entry.as_int(key, min, max),
+            None => Ok(default)
+        }
+    }
+
+    fn pair(&self, key: &str, default: (u32, u32), min: u64, max: u64) -> Result<(u32, u32)> {
+        let entry = match self.entry(key) {
+            Some(entry) => entry,
+            None => return Ok(default)
+        };
+
+        match entry.value {
+            Value::Array(ref array) if array.len() == 2 => {
+                let first = Entry { value: array[0].clone(), ..entry.clone() };
+                let second = Entry { value: array[1].clone(), ..entry.clone() };
+                Ok((try!(first.as_int(key, min, max)) as u32,
+                    try!(second.as_int(key, min, max)) as u32))
+            },
+            _ => entry.error(format!("{} must be a pair of numbers, got {:?}", key, entry.value))
+        }
+    }
+
+    fn check_unused(&self) -> Result<()> {
+        let used = self.used.borrow();
+
+        match self.document.entries.iter().find(|&(key, _)| !used.contains(key)) {
+            Some((key, entry)) => entry.error(format!("unknown key {}", key)),
+            None => Ok(())
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Array(Vec<Value>)
+}
+
+/// Formats the value as it's written in the file.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Str(ref string) => {
+                let escaped = string.replace('\\', "\\\\").replace('"', "\\\"")
+                                    .replace('\n', "\\n").replace('\t', "\\t");
+                write!(f, "\"{}\"", escaped)
+            },
+            Value::Num(num) => write!(f, "{}", num),
+            Value::Bool(flag) => write!(f, "{}", flag),
+            Value::Array(ref array) => {
+                let items = array.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+                write!(f, "[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub value: Value,
+    /// Line in the file, zero for values from the command line.
+    pub line: usize,
+    /// Path to the file or the name of the option.
+    pub origin: Arc<String>
+}
+
+impl Entry {
+    pub fn error<T>(&self, message: String) -> Result<T> {
+        self.fail(Error::Other(message))
+    }
+
+    /// Returns the error located at the entry.
+    pub fn fail<T>(&self, cause: Error) -> Result<T> {
+        Err(Error::Config { origin: self.origin.clone(), line: self.line, cause: Box::new(cause) })
+    }
+
+    pub fn as_num(&self, key: &str, min: f64, max: f64) -> Result<f64> {
+        match self.value {
+            Value::Num(num) if min <= num && num <= max => Ok(num),
+            Value::Num(num) => self.fail(Error::Range {
+                name: key.to_string(),
+                value: num.to_string(),
+                min: min.to_string(),
+                max: max.to_string()
+            }),
+            _ => self.error(format!("{} must be a number, got {:?}", key, self.value))
+        }
+    }
+
+    pub fn as_int(&self, key: &str, min: u64, max: u64) -> Result<u64> {
+        let num = try!(self.as_num(key, min as f64, max as f64));
+
+        if num.fract() != 0. {
+            return self.error(format!("{} must be an integer, got {}", key, num));
+        }
+
+        Ok(num as u64)
+    }
+
+    pub fn as_signed(&self, key: &str, min: i64, max: i64) -> Result<i64> {
+        let num = try!(self.as_num(key, min as f64, max as f64));
+
+        if num.fract() != 0. {
+            return self.error(format!("{} must be an integer, got {}", key, num));
+        }
+
+        Ok(num as i64)
+    }
+
+    pub fn as_int_list(&self, key: &str, min: u64, max: u64) -> Result<Vec<u64>> {
+        match self.value {
+            Value::Array(ref array) => array.iter().map(|value| {
+                Entry { value: value.clone(), ..self.clone() }.as_int(key, min, max)
+            }).collect(),
+            _ => self.error(format!("{} must be a list of numbers, got {:?}", key, self.value))
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str> {
+        match self.value {
+            Value::Str(ref string) => Ok(string),
+            _ => self.error(format!("expected a string, got {:?}", self.value))
+        }
+    }
+
+    pub fn as_str_list(&self) -> Result<Vec<String>> {
+        match self.value {
+            Value::Str(ref string) => Ok(split_list(string)),
+            Value::Array(ref array) => array.iter().map(|value| match *value {
+                Value::Str(ref string) => Ok(string.clone()),
+                _ => self.error(format!("expected a list of strings, got {:?}", self.value))
+            }).collect(),
+            _ => self.error(format!("expected a list of strings, got {:?}", self.value))
+        }
+    }
+}
+
+/// Flat view of a TOML-like file: `[section]` headers, `key = value` pairs and `#` comments.
+/// Keys are stored with a section prefix (`section.key`). Values are strings, numbers, booleans
+/// and one-line arrays of them.
+#[derive(Default)]
+pub struct Document {
+    entries: HashMap<String, Entry>
+}
+
+impl Document {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Document> {
+        let path = path.as_ref();
+        let mut text = String::new();
+
+        try!(File::open(path).and_then(|mut file| file.read_to_string(&mut text)).map_err(|err| {
+            io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+        }));
+
+        Document::parse(&text, &path.display().to_string())
+    }
+
+    pub fn parse(text: &str, origin: &str) -> Result<Document> {
+        let origin = Arc::new(origin.to_string());
+        let mut document = Document::default();
+        let mut section = String::new();
+
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            let error = |message: &str| Error::Config {
+                origin: origin.clone(),
+                line: line,
+                cause: Box::new(Error::Other(message.to_string()))
+            };
+
+            let mut parser = Parser { rest: raw.trim() };
+
+            if parser.eat('[') {
+                section = try!(parser.key().ok_or_else(|| error("expected a section name")));
+
+                if !parser.eat(']') || !parser.is_end() {
+                    return Err(error("malformed section header"));
+                }
+
+                continue;
+            }
+
+            if parser.is_end() {
+                continue;
+            }
+
+            let key = try!(parser.key().ok_or_else(|| error("expected a key")));
+
+            if !parser.eat('=') {
+                return Err(error("expected `=`"));
+            }
+
+            let value = try!(parser.value().ok_or_else(|| error("malformed value")));
+
+            if !parser.is_end() {
+                return Err(error("unexpected characters after the value"));
+            }
+
+            let key = if section.is_empty() { key } else { format!("{}.{}", section, key) };
+            document.entries.insert(key, Entry { value: value, line: line, origin: origin.clone() });
+        }
+
+        Ok(document)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: String, entry: Entry) {
+        self.entries.insert(key, entry);
+    }
+
+    pub fn entries(&self) -> Vec<(&str, &Entry)> {
+        self.entries.iter().map(|(key, entry)| (&key[..], entry)).collect()
+    }
+
+    /// Returns entries of the section with keys relative to it.
+    pub fn section<'a>(&'a self, name: &str) -> Vec<(&'a str, &'a Entry)> {
+        let prefix = format!("{}.", name);
+
+        self.entries.iter()
+            .filter(|&(key, _)| key.starts_with(&prefix))
+            .map(|(key, entry)| (&key[prefix.len()..], entry))
+            .collect()
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str
+}
+
+impl<'a> Parser<'a> {
+    fn skip_spaces(&mut self) {
+        self.rest = self.rest.trim_left();
+    }
+
+    fn is_end(&mut self) -> bool {
+        self.skip_spaces();
+        self.rest.is_empty() || self.rest.starts_with('#')
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        self.skip_spaces();
+
+        if self.rest.starts_with(ch) {
+            self.rest = &self.rest[ch.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn token(&mut self) -> &'a str {
+        self.skip_spaces();
+
+        let end = self.rest.find(|ch: char| !(ch.is_alphanumeric() || "_-.+".contains(ch)))
+                           .unwrap_or(self.rest.len());
+
+        let (token, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        token
+    }
+
+    fn key(&mut self) -> Option<String> {
+        let key = self.token();
+        if key.is_empty() { None } else { Some(key.to_string()) }
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        if self.eat('"') {
+            return self.string().map(Value::Str);
+        }
+
+        if self.eat('[') {
+            let mut array = Vec::new();
+
+            while !self.eat(']') {
+                if !array.is_empty() && !self.eat(',') {
+                    return None;
+                }
+
+                // Allow a trailing comma.
+                if self.eat(']') {
+                    break;
+                }
+
+                match self.value() {
+                    Some(value) => array.push(value),
+                    None => return None
+                }
+            }
+
+            return Some(Value::Array(array));
+        }
+
+        match self.token() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            token => token.parse().ok().map(Value::Num)
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let mut string = String::new();
+        let mut chars = self.rest.char_indices();
+
+        while let Some((index, ch)) = chars.next() {
+            match ch {
+                '"' => {
+                    self.rest = &self.rest[index + 1..];
+                    return Some(string);
+                },
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => string.push('\n'),
+                    Some((_, 't')) => string.push('\t'),
+                    Some((_, ch)) => string.push(ch),
+                    None => return None
+                },
+                ch => string.push(ch)
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base::{Error, Result};
+    use super::{Config, Document, Value, load};
+
+    fn parse(text: &str) -> Document {
+        Document::parse(text, "test.toml").unwrap()
+    }
+
+    fn read(text: &str) -> Result<Config> {
+        Config::read(&parse(text))
+    }
+
+    fn fail<T>(result: Result<T>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err.to_string()
+        }
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn section_header() {
+        let document = parse("top = 1\n[video]\nfps = 30\n\n[sched.ahrs]\nnice = 2");
+
+        assert_eq!(document.get("top").unwrap().value, Value::Num(1.));
+        assert_eq!(document.get("video.fps").unwrap().value, Value::Num(30.));
+        assert_eq!(document.get("sched.ahrs.nice").unwrap().line, 6);
+        assert!(document.get("fps").is_none());
+    }
+
+    #[test]
+    fn string_escapes() {
+        let document = parse(r#"path = "a \"b\"\t#c\\d" # comment"#);
+
+        assert_eq!(document.get("path").unwrap().value, Value::Str("a \"b\"\t#c\\d".to_string()));
+    }
+
+    #[test]
+    fn negative_int() {
+        let document = parse("[sched.ahrs]\nnice = -5");
+        assert_eq!(document.get("sched.ahrs.nice").unwrap().value, Value::Num(-5.));
+
+        let config = read("[sched.ahrs]\nnice = -5").unwrap();
+        assert_eq!(config.sched["ahrs"].nice, Some(-5));
+    }
+
+    #[test]
+    fn trailing_comma() {
+        let document = parse("kinds = [\"video\", \"sysinfo\", ]\nempty = []");
+
+        assert_eq!(document.get("kinds").unwrap().value,
+                   Value::Array(vec![Value::Str("video".to_string()),
+                                     Value::Str("sysinfo".to_string())]));
+        assert_eq!(document.get("empty").unwrap().value, Value::Array(vec![]));
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for text in &["[video", "fps 30", "fps = ", "fps = 30 30", "kinds = [1,, 2]",
+                      "path = \"open"] {
+            match Document::parse(text, "test.toml") {
+                Err(Error::Config { line: 1, .. }) => {},
+                Err(err) => panic!("{:?}: unexpected error {}", text, err),
+                Ok(_) => panic!("{:?} is parsed", text)
+            }
+        }
+    }
+
+    #[test]
+    fn set_unquoted() {
+        let config = load(args(&["--set", "video.device=/dev/video1",
+                                 "--set", "video.fps=30",
+                                 "--set", "player.path=\"a b.rec\""]).into_iter()).unwrap();
+
+        assert_eq!(config.video.device, "/dev/video1");
+        assert_eq!(config.video.fps, 30);
+        assert_eq!(config.player.path, "a b.rec");
+    }
+
+    #[test]
+    fn set_errors() {
+        assert_eq!(fail(load(args(&["--set", "video.fps=0"]).into_iter())),
+                   "--set: video.fps must be in [1, 120], got 0");
+
+        assert!(load(args(&["--set", "video.fps"]).into_iter()).is_err());
+        assert!(load(args(&["--set"]).into_iter()).is_err());
+    }
+
+    #[test]
+    fn range_error() {
+        assert_eq!(fail(read("[ahrs]\nrate = 25\n\n[video]\nfps = 500")),
+                   "test.toml:5: video.fps must be in [1, 120], got 500");
+        assert_eq!(fail(read("[video]\nresolution = [640, 8]")),
+                   "test.toml:2: video.resolution must be in [16, 4096], got 8");
+    }
+
+    #[test]
+    fn unknown_key() {
+        assert_eq!(fail(read("[video]\nfps = 30\nfsp = 30")),
+                   "test.toml:3: unknown key video.fsp");
+        assert_eq!(fail(read("[sched.ahrs]\ncores = [0]")),
+                   "test.toml:2: unknown key sched.ahrs.cores");
+        assert!(read("[profiles]\nheadless = [\"ahrs\"]\n[recorder.topics]\nvideo = [\"\"]")
+                    .is_ok());
+    }
+}
@@ -0,0 +1,149 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::mem;
+use std::panic::{self, PanicInfo};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use backtrace::Backtrace;
+
+use base::config;
+use base::node;
+use messages::Fault;
+
+
+/// Replaces the default panic message by a report of the crashing node: it's written with a
+/// backtrace to `crash.path`, then logged and published as `Fault` by the supervisor (see
+/// `report`). Panics of other threads (relays, listeners...) are queued for `report_unsupervised`.
+pub fn install() {
+    panic::set_hook(Box::new(record));
+}
+
+thread_local!(static SUPERVISED: Cell<bool> = Cell::new(false));
+
+/// Marks the current thread as a node, whose panics are reported by the supervisor.
+pub fn supervised() {
+    SUPERVISED.with(|supervised| supervised.set(true));
+}
+
+/// Logs and publishes the panic of the node after its thread is joined.
+pub fn report(node: &str, payload: &(Any + Send)) {
+    let recorded = get_faults().lock().unwrap().nodes.remove(node);
+
+    publish(recorded.unwrap_or_else(|| Fault {
+        node: node.to_string(),
+        message: describe(payload).to_string(),
+        location: "unknown location".to_string(),
+        report: None
+    }));
+}
+
+/// Logs and publishes panics of unsupervised threads since the last call.
+pub fn report_unsupervised() {
+    let faults = mem::replace(&mut get_faults().lock().unwrap().others, Vec::new());
+
+    for fault in faults {
+        publish(fault);
+    }
+}
+
+fn publish(fault: Fault) {
+    error!("{} panicked at {}: {}", fault.node, fault.location, fault.message);
+
+    if let Some(ref path) = fault.report {
+        info!("the crash report is written to {}", path);
+    }
+
+    node::advertise::<Fault>().send(fault);
+}
+
+fn describe(payload: &(Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown cause"
+    }
+}
+
+// A panic of the hook aborts the process, so it doesn't log (the logger publishes records) and
+// doesn't touch the bus, only writes the file and stderr.
+fn record(info: &PanicInfo) {
+    let node = node::name();
+    let message = describe(info.payload());
+
+    let location = match info.location() {
+        Some(location) => format!("{}:{}", location.file(), location.line()),
+        None => "unknown location".to_string()
+    };
+
+    let result = config::try_get().map(|config| {
+        write_report(&config.crash.path, &node, &location, message)
+    });
+
+    let path = match result {
+        Some(Ok(path)) => Some(path.to_string_lossy().into_owned()),
+        Some(Err(err)) => {
+            let _ = writeln!(io::stderr(), "can't write the crash report: {}", err);
+            None
+        },
+        None => None
+    };
+
+    let fault = Fault {
+        node: node.to_string(),
+        message: message.to_string(),
+        location: location,
+        report: path
+    };
+
+    if let Ok(mut faults) = get_faults().lock() {
+        if SUPERVISED.with(|supervised| supervised.get()) {
+            faults.nodes.insert(fault.node.clone(), fault);
+        } else {
+            faults.others.push(fault);
+        }
+    }
+}
+
+// Panics waiting for `report` (of supervised nodes) and `report_unsupervised` (of the rest).
+struct Faults {
+    nodes: HashMap<String, Fault>,
+    others: Vec<Fault>
+}
+
+fn get_faults() -> &'static Mutex<Faults> {
+    static mut FAULTS: *const Mutex<Faults> = 0 as *const Mutex<Faults>;
+    static ONCE: Once = ONCE_INIT;
+
+    ONCE.call_once(|| {
+        let faults = Faults { nodes: HashMap::new(), others: Vec::new() };
+        unsafe { FAULTS = mem::transmute(Box::new(Mutex::new(faults))) };
+    });
+
+    unsafe { &*FAULTS }
+}
+
+// Written to `<crash.path>/<node>-<unix time>.txt`.
+fn write_report(dir: &str, node: &str, location: &str, message: &str) -> io::Result<PathBuf> {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let dir = Path::new(dir);
+
+    try!(fs::create_dir_all(dir));
+
+    let path = dir.join(format!("{}-{}.txt", node, time));
+    let mut file = try!(File::create(&path));
+
+    try!(writeln!(file, "node: {}", node));
+    try!(writeln!(file, "time: {}", time));
+    try!(writeln!(file, "location: {}", location));
+    try!(writeln!(file, "message: {}", message));
+    try!(writeln!(file, "\n{:?}", Backtrace::new()));
+
+    Ok(path)
+}
@@ -0,0 +1,91 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+
+/// Error of `base::Result`. Callers can match on the kind, `Display` says what happened.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// I/O error of the device on the bus, the register is unknown for opening.
+    Bus { bus: String, addr: u16, reg: Option<u8>, cause: io::Error },
+    /// The device answered with another identity, likely it's another device or it's broken.
+    Identify { bus: String, addr: u16, reg: u8, expected: Vec<u8>, got: Vec<u8> },
+    /// The value is out of the range, e.g. of the config key or the param.
+    Range { name: String, value: String, min: String, max: String },
+    /// The operation, which is described by the string, isn't completed in time.
+    Timeout(String),
+    /// The error of the config at the line of the origin (file or option), zero if unknown.
+    Config { origin: Arc<String>, line: usize, cause: Box<Error> },
+    Other(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "{}", err),
+            Error::Bus { ref bus, addr, reg, ref cause } => match reg {
+                Some(reg) =>
+                    write!(f, "{} at {:#04x}, register {:#04x}: {}", bus, addr, reg, cause),
+                None => write!(f, "{} at {:#04x}: {}", bus, addr, cause)
+            },
+            Error::Identify { ref bus, addr, reg, ref expected, ref got } =>
+                write!(f, "unidentified device {} at {:#04x}: register {:#04x} is {}, expected {}",
+                       bus, addr, reg, hex(got), hex(expected)),
+            Error::Range { ref name, ref value, ref min, ref max } =>
+                write!(f, "{} must be in [{}, {}], got {}", name, min, max, value),
+            Error::Timeout(ref operation) => write!(f, "{} timed out", operation),
+            Error::Config { ref origin, line, ref cause } if line > 0 =>
+                write!(f, "{}:{}: {}", origin, line, cause),
+            Error::Config { ref origin, ref cause, .. } => write!(f, "{}: {}", origin, cause),
+            Error::Other(ref message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(ref err) => err.description(),
+            Error::Bus { .. } => "bus error",
+            Error::Identify { .. } => "unidentified device",
+            Error::Range { .. } => "out of range",
+            Error::Timeout(_) => "timed out",
+            Error::Config { ref cause, .. } => cause.description(),
+            Error::Other(ref message) => message
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            Error::Bus { ref cause, .. } => Some(cause),
+            Error::Config { ref cause, .. } => Some(&**cause),
+            _ => None
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let digits = bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>();
+    format!("0x{}", digits.concat())
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Other(message)
+    }
+}
+
+impl<'a> From<&'a str> for Error {
+    fn from(message: &'a str) -> Error {
+        Error::Other(message.to_string())
+    }
+}
@@ -0,0 +1,107 @@
+use std::mem;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use base::clock::{Clock, Manual};
+use base::config;
+use base::node::{self, Context, Input, Output, Payload, STACK_SIZE};
+
+
+/// Runs nodes in isolation for tests: with private topics, services and params, a manual clock
+/// and its own shutdown. Nodes use the default config unless another one is set before.
+///
+/// A test injects messages into topics, which the node subscribes to, captures topics, which the
+/// node publishes, and moves the clock to drive `node::periodic` loops. Captures must be created
+/// before the node starts publishing.
+pub struct Harness {
+    context: Arc<Context>,
+    clock: Arc<Manual>,
+    nodes: Vec<JoinHandle<()>>
+}
+
+impl Harness {
+    pub fn new() -> Harness {
+        config::set_default();
+
+        let clock = Arc::new(Manual::new(Duration::new(0, 0)));
+
+        Harness {
+            context: Arc::new(Context::new(clock.clone())),
+            clock: clock,
+            nodes: Vec::new()
+        }
+    }
+
+    /// Runs `f` on the current thread as if it were a node of the harness.
+    pub fn enter<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        node::enter(self.context.clone(), f)
+    }
+
+    /// Returns a publisher of the topic for injecting messages.
+    pub fn inject<T: Payload>(&self, topic: &str) -> Output<T> {
+        self.enter(|| node::advertise_as(topic))
+    }
+
+    /// Returns a subscription to the topic for capturing messages.
+    pub fn capture<T: Payload>(&self, topic: &str) -> Input<T> {
+        self.enter(|| node::subscribe_to(topic))
+    }
+
+    /// Starts the node in its own thread named `name`.
+    pub fn spawn(&mut self, name: &str, worker: fn()) {
+        let context = self.context.clone();
+
+        let handle = thread::Builder::new()
+            .name(name.to_string())
+            .stack_size(STACK_SIZE)
+            .spawn(move || node::enter(context, worker)).unwrap();
+
+        self.nodes.push(handle);
+    }
+
+    pub fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    /// Moves the clock, waking nodes waiting for deadlines.
+    pub fn advance(&self, duration: Duration) {
+        self.clock.advance(duration);
+    }
+
+    /// Waits until all nodes sleep on the clock till deadlines after now, so everything due by now
+    /// is done. Only for nodes driven by the clock (e.g. `node::periodic` loops).
+    pub fn settle(&self) {
+        assert!(self.clock.wait_sleeping(self.nodes.len(), Duration::from_secs(10)),
+                "nodes don't settle");
+    }
+
+    /// Requests the shutdown and waits for nodes. Returns the first panic of nodes if any.
+    pub fn stop(mut self) -> thread::Result<()> {
+        self.release();
+
+        let mut result = Ok(());
+
+        for handle in mem::replace(&mut self.nodes, Vec::new()) {
+            let joined = handle.join();
+
+            if result.is_ok() {
+                result = joined;
+            }
+        }
+
+        result
+    }
+
+    fn release(&self) {
+        self.context.shutdown.request();
+        self.clock.release();
+    }
+}
+
+// Don't leave nodes blocked forever if the test fails before `stop`.
+impl Drop for Harness {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
@@ -1,34 +1,404 @@
-use log::{self, Log, LogRecord, LogMetadata, LogLevel, LogLevelFilter, SetLoggerError};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::result;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, Receiver, TryRecvError, TrySendError};
+use std::thread::{self, JoinHandle};
 
+use libc::{self, timespec, tm, clock_gettime, localtime_r, CLOCK_MONOTONIC, CLOCK_REALTIME};
+use libc::{sigset_t, sigfillset, pthread_sigmask, SIG_BLOCK};
+use log::{self, Log, LogRecord, LogMetadata, LogLevel, LogLevelFilter, MaxLogLevelFilter};
+use log::SetLoggerError;
+
+use base::Result;
+use base::config::LogConfig;
+use base::node::{self, Context, Output};
+use messages::LogEntry;
+
+
+/// Overrides `log.levels` of the config.
+pub const ENV_VAR: &'static str = "HODOK_LOG";
+
+/// How many lines can wait for the file writer, the rest are dropped.
+pub const FILE_QUEUE_SIZE: usize = 1024;
+
+const DEFAULT_LEVEL: LogLevelFilter = LogLevelFilter::Debug;
 
 macro_rules! stylish {
     ($data:expr, $style:expr) => (concat!("\x1b[", $style, "m", $data, "\x1b[0m"))
 }
 
-struct Logger;
+/// Levels of nodes (names of threads, modules for unnamed ones) like `info,ahrs=trace,server=warn`.
+///
+/// The item without a name sets the level of the rest.
+pub struct Levels {
+    default: LogLevelFilter,
+    nodes: HashMap<String, LogLevelFilter>
+}
+
+impl Default for Levels {
+    fn default() -> Levels {
+        Levels { default: DEFAULT_LEVEL, nodes: HashMap::new() }
+    }
+}
+
+impl Levels {
+    pub fn parse(spec: &str) -> result::Result<Levels, String> {
+        let mut levels = Levels::default();
+
+        for item in spec.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            let mut split = item.splitn(2, '=');
+            let first = split.next().unwrap().trim();
+
+            let (node, level) = match split.next() {
+                Some(level) => (Some(first), level.trim()),
+                None => (None, first)
+            };
+
+            let level = try!(LogLevelFilter::from_str(level).map_err(|_| {
+                format!("unknown log level \"{}\", expected off, error, warn, info, debug or trace",
+                        level)
+            }));
+
+            match node {
+                Some(node) => { levels.nodes.insert(node.to_string(), level); },
+                None => levels.default = level
+            }
+        }
+
+        Ok(levels)
+    }
+
+    fn get(&self, node: &str) -> LogLevelFilter {
+        *self.nodes.get(node).unwrap_or(&self.default)
+    }
+
+    fn max(&self) -> LogLevelFilter {
+        self.nodes.values().fold(self.default, |max, &level| if level > max { level } else { max })
+    }
+}
+
+struct Logger {
+    levels: RwLock<(Levels, MaxLogLevelFilter)>,
+    file: Mutex<Option<SyncSender<String>>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+    dropped: Arc<AtomicUsize>,
+    /// Records are published after the config is set, because the bus relies on it.
+    publishing: AtomicBool,
+    colored: bool
+}
 
 impl Log for Logger {
-    fn enabled(&self, _: &LogMetadata) -> bool { true }
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= self.levels.read().unwrap().0.get(&node_of(metadata.target()))
+    }
 
     fn log(&self, record: &LogRecord) {
-        let marker = match record.level() {
-            LogLevel::Error => stylish!("•", "31"),
-            LogLevel::Warn  => stylish!("•", "33"),
-            LogLevel::Info  => stylish!("•", "34"),
-            LogLevel::Debug => stylish!("•", "35"),
-            LogLevel::Trace => stylish!("•", "37")
-        };
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let (wall, monotonic) = (wall_time(), monotonic_time());
+        let node = node_of(record.target());
+        let message = record.args().to_string();
+
+        let plain = format!("{} {} {:5} {:10} | {}", wall, monotonic, record.level(), node, message);
 
-        let node = record.location().module_path().rsplit_terminator(':').next().unwrap();
-        let message = record.args();
+        // The file is written by its own thread, so slow storage never blocks nodes.
+        if let Some(ref file) = *self.file.lock().unwrap() {
+            if let Err(TrySendError::Full(_)) = file.try_send(plain.clone()) {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
 
-        println!("{} {:10} | {}", marker, node, message);
+        if self.colored {
+            let marker = match record.level() {
+                LogLevel::Error => stylish!("•", "31"),
+                LogLevel::Warn  => stylish!("•", "33"),
+                LogLevel::Info  => stylish!("•", "34"),
+                LogLevel::Debug => stylish!("•", "35"),
+                LogLevel::Trace => stylish!("•", "37")
+            };
+
+            println!(concat!(stylish!("{} {}", "90"), " {} {:10} | {}"),
+                     wall, monotonic, marker, node, message);
+        } else {
+            println!("{}", plain);
+        }
+
+        if self.publishing.load(Ordering::Relaxed) {
+            publish(LogEntry { level: record.level(), node: node, text: message });
+        }
     }
 }
 
-pub fn init() -> Result<(), SetLoggerError> {
+/// Sets up the logger with levels from the environment or the default ones.
+pub fn init() -> result::Result<(), SetLoggerError> {
+    let levels = match env::var(ENV_VAR) {
+        Ok(spec) => Levels::parse(&spec).unwrap_or_else(|err| {
+            println!("{}: {}", ENV_VAR, err);
+            Levels::default()
+        }),
+        Err(_) => Levels::default()
+    };
+
     log::set_logger(|max_log_level| {
-        max_log_level.set(LogLevelFilter::Debug);
-        Box::new(Logger)
+        max_log_level.set(levels.max());
+
+        let logger = Box::new(Logger {
+            levels: RwLock::new((levels, max_log_level)),
+            file: Mutex::new(None),
+            writer: Mutex::new(None),
+            dropped: Arc::new(AtomicUsize::new(0)),
+            publishing: AtomicBool::new(false),
+            colored: unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+        });
+
+        unsafe { LOGGER = &*logger };
+        logger
     })
 }
+
+/// Applies the config: levels unless they are set by the environment and the file sink.
+///
+/// Also starts publishing records as `LogEntry`, so the config must be set before.
+pub fn configure(config: &LogConfig) -> Result<()> {
+    let logger = match unsafe { LOGGER.as_ref() } {
+        Some(logger) => logger,
+        None => return Ok(())
+    };
+
+    if env::var(ENV_VAR).is_err() {
+        // The spec is validated by the config.
+        let levels = Levels::parse(&config.levels).unwrap();
+        let mut guard = logger.levels.write().unwrap();
+
+        guard.1.set(levels.max());
+        guard.0 = levels;
+    }
+
+    if let Some(ref path) = config.path {
+        let sink = try!(FileSink::open(PathBuf::from(path), config.max_size * 1024, config.keep)
+            .map_err(|err| format!("log file {}: {}", path, err)));
+
+        let (tx, rx) = mpsc::sync_channel(FILE_QUEUE_SIZE);
+        let dropped = logger.dropped.clone();
+
+        let handle = try!(thread::Builder::new()
+            .name("logger".to_string())
+            .spawn(move || write_file(sink, rx, dropped)));
+
+        *logger.file.lock().unwrap() = Some(tx);
+        *logger.writer.lock().unwrap() = Some(handle);
+    }
+
+    logger.publishing.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Writes the rest of lines to the file and closes it, must be called before exit.
+pub fn flush() {
+    let logger = match unsafe { LOGGER.as_ref() } {
+        Some(logger) => logger,
+        None => return
+    };
+
+    logger.file.lock().unwrap().take();
+
+    if let Some(handle) = logger.writer.lock().unwrap().take() {
+        let _ = handle.join();
+    }
+}
+
+static mut LOGGER: *const Logger = 0 as *const Logger;
+
+thread_local!(static PUBLISHING: Cell<bool> = Cell::new(false));
+
+// Advertising takes the registry, so the output is kept along with the context it belongs to.
+thread_local!(static OUTPUT: RefCell<Option<(Arc<Context>, Output<LogEntry>)>> =
+                  RefCell::new(None));
+
+// Publishing must not log itself, otherwise it would recurse, so nested records are only printed.
+fn publish(entry: LogEntry) {
+    PUBLISHING.with(|publishing| {
+        if publishing.get() {
+            return;
+        }
+
+        publishing.set(true);
+
+        OUTPUT.with(|output| {
+            let context = node::context();
+            let mut output = output.borrow_mut();
+
+            // The thread can enter another context (e.g. of `base::harness`).
+            let stale = output.as_ref().map_or(true, |&(ref cached, _)| {
+                &**cached as *const Context != &*context as *const Context
+            });
+
+            if stale {
+                *output = Some((context, node::advertise()));
+            }
+
+            output.as_ref().unwrap().1.send(entry);
+        });
+
+        publishing.set(false);
+    });
+}
+
+// Threads spawned by nodes are usually unnamed, use the module then.
+fn node_of(target: &str) -> String {
+    match thread::current().name() {
+        Some(name) => name.to_string(),
+        None => target.rsplit_terminator(':').next().unwrap().to_string()
+    }
+}
+
+// Like `2016-03-01 12:34:56.789`.
+fn wall_time() -> String {
+    let mut ts = timespec { tv_sec: 0, tv_nsec: 0 };
+    let mut tm: tm = unsafe { mem::zeroed() };
+
+    unsafe {
+        clock_gettime(CLOCK_REALTIME, &mut ts);
+        localtime_r(&ts.tv_sec, &mut tm);
+    }
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}", tm.tm_year + 1900, tm.tm_mon + 1,
+            tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec, ts.tv_nsec / 1000000)
+}
+
+// Seconds since boot like `[  123.456]`, matching stamps of messages with the default clock.
+fn monotonic_time() -> String {
+    let mut ts = timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { clock_gettime(CLOCK_MONOTONIC, &mut ts) };
+    format!("[{:5}.{:03}]", ts.tv_sec, ts.tv_nsec / 1000000)
+}
+
+fn write_file(mut sink: FileSink, rx: Receiver<String>, dropped: Arc<AtomicUsize>) {
+    // The writer is started before `shutdown::install`, keep signals for its thread.
+    unsafe {
+        let mut set: sigset_t = mem::zeroed();
+        sigfillset(&mut set);
+        pthread_sigmask(SIG_BLOCK, &set, 0 as *mut sigset_t);
+    }
+
+    loop {
+        let line = match rx.try_recv() {
+            Ok(line) => line,
+            Err(TryRecvError::Empty) => {
+                // Flush only when idle to write in large blocks under load.
+                if let Err(err) = sink.flush() {
+                    return sink.fail(err);
+                }
+
+                match rx.recv() {
+                    Ok(line) => line,
+                    Err(_) => break
+                }
+            },
+            Err(TryRecvError::Disconnected) => break
+        };
+
+        let count = dropped.swap(0, Ordering::Relaxed);
+
+        if count > 0 {
+            if let Err(err) = sink.write(&format!("{} lines are dropped", count)) {
+                return sink.fail(err);
+            }
+        }
+
+        if let Err(err) = sink.write(&line) {
+            return sink.fail(err);
+        }
+    }
+
+    if let Err(err) = sink.flush() {
+        sink.fail(err);
+    }
+}
+
+/// Appends lines to the file, moving it to `<path>.1` when it exceeds the size. Older files are
+/// shifted up to `<path>.<keep>`, the last one is removed.
+struct FileSink {
+    path: PathBuf,
+    max_size: u64,
+    keep: u32,
+    file: BufWriter<File>,
+    size: u64
+}
+
+impl FileSink {
+    fn open(path: PathBuf, max_size: u64, keep: u32) -> io::Result<FileSink> {
+        let file = try!(OpenOptions::new().append(true).create(true).open(&path));
+        let size = try!(file.metadata()).len();
+
+        Ok(FileSink {
+            path: path,
+            max_size: max_size,
+            keep: keep,
+            file: BufWriter::new(file),
+            size: size
+        })
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let length = line.len() as u64 + 1;
+
+        if self.size > 0 && self.size + length > self.max_size {
+            try!(self.rotate());
+        }
+
+        try!(writeln!(self.file, "{}", line));
+        self.size += length;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        try!(self.file.flush());
+
+        if self.keep > 0 {
+            for index in (1..self.keep).rev() {
+                let from = self.rotated(index);
+
+                if from.exists() {
+                    try!(fs::rename(from, self.rotated(index + 1)));
+                }
+            }
+
+            try!(fs::rename(&self.path, self.rotated(1)));
+        }
+
+        let file = try!(OpenOptions::new().write(true).truncate(true).create(true)
+                                          .open(&self.path));
+
+        self.file = BufWriter::new(file);
+        self.size = 0;
+
+        Ok(())
+    }
+
+    fn rotated(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    // Errors can't be logged to the file itself, report to the console and stop writing.
+    fn fail(&self, err: io::Error) {
+        println!("log file {}: {}, stopping writing", self.path.display(), err);
+    }
+}
@@ -1,9 +1,22 @@
-use std::error;
 use std::result;
 
 #[macro_use]
 pub mod node;
+pub mod clock;
+pub mod config;
+pub mod crash;
+pub mod error;
+pub mod harness;
 pub mod logger;
+pub mod params;
+pub mod sched;
+pub mod shutdown;
+pub mod supervisor;
+pub mod sync;
+pub mod watchdog;
+pub mod wire;
 
 
-pub type Result<T> = result::Result<T, Box<error::Error + Send + Sync>>;
+pub use self::error::Error;
+
+pub type Result<T> = result::Result<T, Error>;
@@ -1,90 +1,849 @@
 use std::any::{TypeId, Any};
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::intrinsics;
 use std::mem;
-use std::sync::Arc;
-use std::sync::mpsc::{self, Sender, Receiver};
-use std::sync::{Once, ONCE_INIT};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender, SyncSender, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, Once, ONCE_INIT};
 use std::thread;
 use std::time::Duration;
 
+use base::{Error, Result};
+use base::clock::{self, Clock};
+use base::params;
+use base::shutdown;
+
 
 pub const STACK_SIZE: usize = 64 * 1024;
 
-macro_rules! run_nodes {
-    ($node:ident $($nodes:ident)*) => {
-        let _ = ::std::thread::Builder::new()
-            .name(stringify!($node).to_string())
-            .stack_size(::base::node::STACK_SIZE)
-            .spawn(::nodes::$node::worker).unwrap();
-        info!("starting {}", stringify!($node));
-        run_nodes!($($nodes)*);
-    };
+/// Subscription to a topic, dereferences to the receiver (e.g. for `select!`).
+///
+/// Dropping it closes the queue, so the relay stops and the topic forgets the subscriber even if
+/// nothing is published anymore.
+pub struct Input<I> {
+    rx: Receiver<Arc<Message<I>>>,
+    queue: Arc<Queue<Message<I>>>
+}
 
-    () => ({
-        let (_tx, rx) = ::std::sync::mpsc::channel::<()>();
-        rx.recv().unwrap();
-    });
+/// Data, which can be sent over topics.
+pub trait Payload: Send + Sync + Any {
+    /// Returns the size of the data in bytes, used for statistics only.
+    fn size(&self) -> usize {
+        mem::size_of_val(self)
+    }
 }
 
-pub type Input<I> = Receiver<Arc<I>>;
-pub struct Output<O: Send + Sync>(Vec<Sender<Arc<O>>>);
+/// Publishing end of a topic, can be cloned and moved between threads.
+pub struct Output<O: Send + Sync>(Arc<Topic<O>>);
 
-impl<O: Send + Sync> Output<O> {
+struct Topic<O: Send + Sync> {
+    name: String,
+    type_name: &'static str,
+    state: Mutex<TopicState<O>>,
+    seq: AtomicUsize
+}
+
+struct TopicState<O: Send + Sync> {
+    publishers: Vec<Arc<String>>,
+    subscribers: Vec<Subscriber<O>>,
+    meter: Meter,
+    /// Expected rate [Hz] to be checked by the watchdog.
+    expected: Option<f32>,
+    /// Time of the last message or the expectation [ns].
+    last: u64
+}
+
+pub struct Message<T> {
+    /// Time of capture by the clock of the context (see `base::clock`), e.g. scaled by
+    /// `clock.scale` or moved manually in tests.
+    pub stamp: Duration,
+    /// Sequence number within the topic, gaps mean dropped messages.
+    pub seq: u32,
+    /// Name of the publishing node.
+    pub source: Arc<String>,
+    pub data: T
+}
+
+impl<I> Deref for Input<I> {
+    type Target = Receiver<Arc<Message<I>>>;
+
+    fn deref(&self) -> &Receiver<Arc<Message<I>>> {
+        &self.rx
+    }
+}
+
+impl<I> Drop for Input<I> {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+impl<T> Deref for Message<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+struct Subscriber<O: Send + Sync> {
+    node: Arc<String>,
+    policy: Policy,
+    limiter: Limiter,
+    queue: Arc<Queue<Message<O>>>
+}
+
+impl<O: Send + Sync> Clone for Output<O> {
+    fn clone(&self) -> Output<O> {
+        Output(self.0.clone())
+    }
+}
+
+impl<O: Payload> Output<O> {
     pub fn send(&self, data: O) {
-        match self.0.len() {
-            0 => {},
-            1 => unsafe { self.0.get_unchecked(0).send(Arc::new(data)).unwrap(); },
-            _ => {
-                let arc = Arc::new(data);
-
-                for receiver in &self.0 {
-                    receiver.send(arc.clone()).unwrap();
+        self.send_at(data, now());
+    }
+
+    /// Sends the data captured at `stamp`, which must be obtained by `now()`.
+    pub fn send_at(&self, data: O, stamp: Duration) {
+        let size = data.size();
+
+        let message = Arc::new(Message {
+            stamp: stamp,
+            seq: self.0.seq.fetch_add(1, Ordering::Relaxed) as u32,
+            source: name(),
+            data: data
+        });
+
+        let mut state = self.0.state.lock().unwrap();
+        let time = nanos(now());
+        state.meter.add(time, size);
+        state.last = time;
+
+        // Receivers can be dropped at any time, so prune them here.
+        state.subscribers.retain(|subscriber| {
+            !subscriber.limiter.pass(stamp) || subscriber.queue.push(message.clone())
+        });
+    }
+}
+
+impl<O: Send + Sync> Output<O> {
+    /// Declares the rate of publishing, so the watchdog reports the topic if it goes silent.
+    ///
+    /// Should be called again after changing the rate. The silence is counted from now.
+    pub fn expect_rate(&self, rate: f32) {
+        assert!(rate > 0.);
+
+        let mut state = self.0.state.lock().unwrap();
+        state.expected = Some(rate);
+        state.last = nanos(now());
+    }
+}
+
+/// Call of a service, the handler must answer it by `reply`.
+pub struct Request<Req, Resp> {
+    /// Name of the calling node.
+    pub source: Arc<String>,
+    pub data: Req,
+    reply: Sender<Resp>
+}
+
+impl<Req, Resp> Request<Req, Resp> {
+    /// Answers the call. The caller can have already given up, then the response is dropped.
+    pub fn reply(self, resp: Resp) {
+        let _ = self.reply.send(resp);
+    }
+}
+
+impl<Req, Resp> Deref for Request<Req, Resp> {
+    type Target = Req;
+
+    fn deref(&self) -> &Req {
+        &self.data
+    }
+}
+
+/// Serving end of a service, can be used in `select!` along with inputs.
+pub type Service<Req, Resp> = Receiver<Request<Req, Resp>>;
+
+// Rates are averaged over about one second.
+#[derive(Default)]
+struct Meter {
+    total: usize,
+    start: u64,
+    count: usize,
+    bytes: usize,
+    rate: f32,
+    bandwidth: f32
+}
+
+impl Meter {
+    fn add(&mut self, now: u64, bytes: usize) {
+        let elapsed = now.saturating_sub(self.start);
+
+        if elapsed >= 1000000000 {
+            self.rate = self.count as f32 / (elapsed as f32 * 1e-9);
+            self.bandwidth = self.bytes as f32 / (elapsed as f32 * 1e-9);
+            self.start = now;
+            self.count = 0;
+            self.bytes = 0;
+        }
+
+        self.total += 1;
+        self.count += 1;
+        self.bytes += bytes;
+    }
+
+    fn rates(&self, now: u64) -> (f32, f32) {
+        // The topic is silent.
+        if now.saturating_sub(self.start) >= 2000000000 { (0., 0.) } else { (self.rate, self.bandwidth) }
+    }
+}
+
+pub struct TopicInfo {
+    pub type_name: &'static str,
+    pub topic: String,
+    pub publishers: Vec<Arc<String>>,
+    pub subscribers: Vec<SubscriberInfo>,
+    pub total: usize,
+    pub rate: f32,          // [msg/s]
+    pub bandwidth: f32,     // [B/s]
+    pub expected_rate: Option<f32>,
+    /// Time since the last message or the expectation.
+    pub silence: Duration
+}
+
+impl TopicInfo {
+    /// Returns the name like `Type/topic` or `Type` for the unnamed topic.
+    pub fn id(&self) -> String {
+        if self.topic.is_empty() {
+            self.type_name.to_string()
+        } else {
+            format!("{}/{}", self.type_name, self.topic)
+        }
+    }
+}
+
+pub struct SubscriberInfo {
+    pub node: Arc<String>,
+    pub policy: Policy,
+    pub limit: Limit,
+    /// Pending messages, not counting the one waiting in the relay.
+    pub queued: usize,
+    pub dropped: usize
+}
+
+trait Inspect: Send + Sync {
+    fn inspect(&self) -> TopicInfo;
+}
+
+impl<O: Send + Sync> Inspect for Topic<O> {
+    fn inspect(&self) -> TopicInfo {
+        let mut state = self.state.lock().unwrap();
+        let time = nanos(now());
+        let (rate, bandwidth) = state.meter.rates(time);
+        let silence = time.saturating_sub(state.last);
+
+        state.subscribers.retain(|subscriber| !subscriber.queue.is_closed());
+
+        TopicInfo {
+            type_name: self.type_name,
+            topic: self.name.clone(),
+            publishers: state.publishers.clone(),
+            subscribers: state.subscribers.iter().map(|subscriber| SubscriberInfo {
+                node: subscriber.node.clone(),
+                policy: subscriber.policy,
+                limit: subscriber.limiter.limit,
+                queued: subscriber.queue.len(),
+                dropped: subscriber.queue.dropped.load(Ordering::Relaxed)
+            }).collect(),
+            total: state.meter.total,
+            rate: rate,
+            bandwidth: bandwidth,
+            expected_rate: state.expected,
+            silence: Duration::new(silence / 1000000000, (silence % 1000000000) as u32)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    /// Never drop anything (the default for `subscribe`).
+    Unbounded,
+    /// Keep at most `n` pending messages, discarding the oldest one on overflow.
+    DropOldest(usize),
+    /// Keep at most `n` pending messages, discarding incoming ones on overflow.
+    DropNewest(usize),
+    /// Keep the most recent message only.
+    KeepLatest
+}
+
+/// Which messages are delivered to a subscriber, applied before the policy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    /// Deliver all messages (the default).
+    None,
+    /// Deliver at most the given rate [Hz] judging by stamps.
+    MaxRate(f32),
+    /// Deliver every n-th message, starting with the first one.
+    EveryNth(u32)
+}
+
+// The state lives in cells, because subscribers are visited by `retain`, which gives `&` only.
+// They are accessed under the lock of the topic anyway.
+struct Limiter {
+    limit: Limit,
+    next: Cell<u64>,
+    count: Cell<u32>
+}
+
+impl Limiter {
+    fn new(limit: Limit) -> Limiter {
+        match limit {
+            Limit::MaxRate(rate) => assert!(rate > 0.),
+            Limit::EveryNth(n) => assert!(n > 0),
+            Limit::None => {}
+        }
+
+        Limiter { limit: limit, next: Cell::new(0), count: Cell::new(0) }
+    }
+
+    fn pass(&self, stamp: Duration) -> bool {
+        match self.limit {
+            Limit::None => true,
+            Limit::MaxRate(rate) => {
+                let (stamp, next) = (nanos(stamp), self.next.get());
+
+                if stamp < next {
+                    return false;
                 }
+
+                // Keep the grid to hold the rate, but don't catch up after a pause.
+                let period = (1e9 / rate as f64) as u64;
+                self.next.set(if next + period > stamp { next + period } else { stamp + period });
+                true
+            },
+            Limit::EveryNth(n) => {
+                let count = self.count.get();
+                self.count.set((count + 1) % n);
+                count == 0
             }
+        }
+    }
+}
+
+/// Number of messages discarded by the policy of a subscription.
+#[derive(Clone)]
+pub struct Dropped(Arc<AtomicUsize>);
+
+impl Dropped {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// `select!` works with `Receiver` only, so subscriptions are served by a relay thread, which moves
+// messages from the queue to a rendezvous channel. Thus the receiver can get one message more than
+// the capacity: the one waiting in the relay. Unlike a plain channel, the queue can be inspected
+// for pending messages, unbounded subscriptions included.
+struct Queue<O> {
+    policy: Policy,
+    state: Mutex<QueueState<O>>,
+    cond: Condvar,
+    dropped: Arc<AtomicUsize>
+}
+
+struct QueueState<O> {
+    buf: VecDeque<Arc<O>>,
+    closed: bool
+}
+
+impl<O> Queue<O> {
+    fn push(&self, data: Arc<O>) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if state.closed {
+            return false;
+        }
+
+        let capacity = match self.policy {
+            Policy::Unbounded => usize::max_value(),
+            Policy::DropOldest(n) | Policy::DropNewest(n) => n,
+            Policy::KeepLatest => 1
         };
+
+        if state.buf.len() >= capacity {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+
+            if let Policy::DropNewest(_) = self.policy {
+                return true;
+            }
+
+            state.buf.pop_front();
+        }
+
+        state.buf.push_back(data);
+        self.cond.notify_one();
+        true
+    }
+
+    /// Waits for the next message, returns `None` once the queue is closed.
+    fn pop(&self) -> Option<Arc<O>> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if state.closed {
+                return None;
+            }
+
+            if let Some(data) = state.buf.pop_front() {
+                return Some(data);
+            }
+
+            state = self.cond.wait(state).unwrap();
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.state.lock().unwrap().buf.len()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.buf.clear();
+        self.cond.notify_all();
     }
 }
 
-pub fn advertise<T: Send + Sync + Any>() -> &'static mut Output<T> {
-    get_output()
+fn relay<O>(queue: Arc<Queue<O>>, tx: SyncSender<Arc<O>>) {
+    while let Some(data) = queue.pop() {
+        if tx.send(data).is_err() {
+            queue.close();
+            break;
+        }
+    }
 }
 
-pub fn subscribe<T: Send + Sync + Any + 'static>() -> Input<T> {
-    let mut output = get_output::<T>();
-    let (rx, tx) = mpsc::channel();
-    output.0.push(rx);
-    tx
+pub fn advertise<T: Payload>() -> Output<T> {
+    advertise_as("")
 }
 
-fn get_output<T: Send + Sync + Any>() -> &'static mut Output<T> {
-    // Associated statics are not yet implemented and generics over statics are forbidden, hence
-    // we can use one static pointer to `HashMap<TypeId, Box<Any>>` (aka `AnyMap`).
-    type AnyMap = HashMap<TypeId, Box<Any>>;
-    static mut OUTPUT_MAP: *mut AnyMap = 0 as *mut AnyMap;
-    static ONCE: Once = ONCE_INIT;
+pub fn advertise_as<T: Payload>(topic: &str) -> Output<T> {
+    let topic = get_topic::<T>(topic);
 
-    ONCE.call_once(|| {
-        unsafe { OUTPUT_MAP = mem::transmute(Box::new(AnyMap::new())) };
+    {
+        let mut state = topic.state.lock().unwrap();
+        let node = name();
+
+        if !state.publishers.contains(&node) {
+            state.publishers.push(node);
+        }
+    }
+
+    Output(topic)
+}
+
+pub fn subscribe<T: Payload>() -> Input<T> {
+    subscribe_to("")
+}
+
+pub fn subscribe_to<T: Payload>(topic: &str) -> Input<T> {
+    subscribe_with(topic, Policy::Unbounded).0
+}
+
+pub fn subscribe_with<T: Payload>(topic: &str, policy: Policy) -> (Input<T>, Dropped) {
+    subscribe_limited(topic, policy, Limit::None)
+}
+
+/// Subscribes to the topic, skipping messages by the limit on the publisher side.
+pub fn subscribe_limited<T: Payload>(topic: &str, policy: Policy, limit: Limit)
+    -> (Input<T>, Dropped)
+{
+    match policy {
+        Policy::DropOldest(n) | Policy::DropNewest(n) => assert!(n > 0),
+        _ => {}
+    }
+
+    let limiter = Limiter::new(limit);
+    let dropped = Arc::new(AtomicUsize::new(0));
+
+    let queue = Arc::new(Queue::<Message<T>> {
+        policy: policy,
+        state: Mutex::new(QueueState { buf: VecDeque::new(), closed: false }),
+        cond: Condvar::new(),
+        dropped: dropped.clone()
     });
 
-    let static_ref = unsafe { &mut *OUTPUT_MAP };
+    let (tx, rx) = mpsc::sync_channel(0);
+    let relay_queue = queue.clone();
+    thread::spawn(move || relay(relay_queue, tx));
 
-    static_ref.entry(TypeId::of::<T>()).or_insert_with(|| {
-        let output = Output::<T>(Vec::with_capacity(1));
-        Box::new(output)
-    }).downcast_mut::<Output<T>>().unwrap()
+    add_subscriber::<T>(topic, policy, limiter, queue.clone());
+    (Input { rx: rx, queue: queue }, Dropped(dropped))
 }
 
-pub fn periodic(rate: f32) -> Receiver<()> {
-    let period = (1e9/rate).ceil() as u32;
+fn add_subscriber<T: Payload>(topic: &str, policy: Policy, limiter: Limiter,
+                              queue: Arc<Queue<Message<T>>>)
+{
+    let topic = get_topic::<T>(topic);
+    let mut state = topic.state.lock().unwrap();
+
+    state.subscribers.push(Subscriber {
+        node: name(),
+        policy: policy,
+        limiter: limiter,
+        queue: queue
+    });
+}
+
+/// Registers the service `name`, replacing the previous handler (e.g. of the restarted node).
+pub fn serve<Req: Send + Any, Resp: Send + Any>(name: &str) -> Service<Req, Resp> {
+    let (tx, rx) = mpsc::channel::<Request<Req, Resp>>();
+    context().services.lock().unwrap().insert(name.to_string(), Box::new(tx));
+    rx
+}
+
+/// Calls the service `name` and waits for the response at most `timeout`.
+pub fn call<Req: Send + Any, Resp: Send + Any>(service: &str, data: Req, timeout: Duration)
+    -> Result<Resp>
+{
     let (tx, rx) = mpsc::channel();
 
-    thread::spawn(move || {
-        loop {
-            thread::sleep(Duration::new(0, period));
-            tx.send(()).unwrap();
+    let request = Request {
+        source: name(),
+        data: data,
+        reply: tx
+    };
+
+    {
+        let context = context();
+        let mut services = context.services.lock().unwrap();
+
+        let handler = match services.get(service) {
+            Some(handler) => match handler.downcast_ref::<Sender<Request<Req, Resp>>>() {
+                Some(handler) => handler.clone(),
+                None => return Err(format!("service \"{}\" has other types", service).into())
+            },
+            None => return Err(format!("no service \"{}\"", service).into())
+        };
+
+        // The handling node has stopped, forget about it.
+        if handler.send(request).is_err() {
+            services.remove(service);
+            return Err(format!("service \"{}\" is stopped", service).into());
         }
+    }
+
+    match rx.recv_timeout(timeout) {
+        Ok(resp) => Ok(resp),
+        Err(RecvTimeoutError::Timeout) => Err(Error::Timeout(format!("service \"{}\"", service))),
+        Err(RecvTimeoutError::Disconnected) =>
+            Err(format!("service \"{}\" dropped the request", service).into())
+    }
+}
+
+/// Returns names of all registered services sorted.
+pub fn services() -> Vec<String> {
+    let mut services = context().services.lock().unwrap().keys().cloned().collect::<Vec<_>>();
+    services.sort();
+    services
+}
+
+/// Returns statistics of all topics sorted by type and name.
+pub fn topics() -> Vec<TopicInfo> {
+    // Don't hold the registry while inspecting topics.
+    let context = context();
+    let inspects = context.topics.lock().unwrap().values()
+        .map(|&(_, ref inspect)| inspect.clone())
+        .collect::<Vec<_>>();
+
+    let mut topics = inspects.iter().map(|inspect| inspect.inspect()).collect::<Vec<_>>();
+
+    topics.sort_by(|a, b| (a.type_name, &a.topic).cmp(&(b.type_name, &b.topic)));
+    topics
+}
+
+pub fn log_topics() {
+    for topic in topics() {
+        let publishers = topic.publishers.iter().map(|x| &x[..]).collect::<Vec<_>>();
+
+        info!("{}: {:.1}msg/s, {:.1}KiB/s, {} total, from [{}]",
+              topic.id(), topic.rate, topic.bandwidth / 1024., topic.total, publishers.join(", "));
+
+        for subscriber in topic.subscribers {
+            info!("    -> {}: {:?}, {:?}, queued {}, dropped {}",
+                  subscriber.node, subscriber.policy, subscriber.limit, subscriber.queued,
+                  subscriber.dropped);
+        }
+    }
+
+    info!("services: [{}]", services().join(", "));
+}
+
+type Registry = Mutex<HashMap<(TypeId, String), (Box<Any + Send>, Arc<Inspect>)>>;
+type Services = Mutex<HashMap<String, Box<Any + Send>>>;
+
+/// Topics, services, params, the clock and the shutdown state shared by nodes.
+///
+/// All nodes use the process-wide context, unless a thread enters another one (see
+/// `base::harness`). Threads spawned by nodes don't inherit the context, thus they must get
+/// everything they need (inputs, outputs, shutdown watchers) before spawning.
+pub struct Context {
+    topics: Registry,
+    services: Services,
+    pub clock: Arc<Clock>,
+    pub shutdown: shutdown::State,
+    pub params: params::Store
+}
+
+impl Context {
+    pub fn new(clock: Arc<Clock>) -> Context {
+        Context {
+            topics: Registry::new(HashMap::new()),
+            services: Services::new(HashMap::new()),
+            clock: clock,
+            shutdown: shutdown::State::new(),
+            params: params::Store::new()
+        }
+    }
+}
+
+thread_local!(static CONTEXT: RefCell<Option<Arc<Context>>> = RefCell::new(None));
+
+/// Returns the context of the current thread.
+pub fn context() -> Arc<Context> {
+    static mut GLOBAL: *const Arc<Context> = 0 as *const Arc<Context>;
+    static ONCE: Once = ONCE_INIT;
+
+    if let Some(context) = CONTEXT.with(|context| context.borrow().clone()) {
+        return context;
+    }
+
+    ONCE.call_once(|| {
+        let context = Arc::new(Context::new(clock::configured()));
+        unsafe { GLOBAL = mem::transmute(Box::new(context)) };
     });
 
-    rx
+    unsafe { (*GLOBAL).clone() }
+}
+
+/// Runs `f` in the context on the current thread.
+pub fn enter<R, F: FnOnce() -> R>(context: Arc<Context>, f: F) -> R {
+    let previous = CONTEXT.with(|current| mem::replace(&mut *current.borrow_mut(), Some(context)));
+
+    // Restore the previous context even if `f` panics.
+    struct Guard(Option<Arc<Context>>);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CONTEXT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let _guard = Guard(previous);
+    f()
+}
+
+fn get_topic<T: Payload>(topic: &str) -> Arc<Topic<T>> {
+    // Associated statics are not yet implemented and generics over statics are forbidden, hence
+    // we can use one static registry with `Box<Any>` values (aka `AnyMap`) keyed by `TypeId`.
+    // Type-only calls use the unnamed topic, so `advertise::<T>()` is `advertise_as::<T>("")`.
+    let context = context();
+    let mut registry = context.topics.lock().unwrap();
+
+    let entry = registry.entry((TypeId::of::<T>(), topic.to_string())).or_insert_with(|| {
+        let type_name = unsafe { intrinsics::type_name::<T>() };
+
+        let topic = Arc::new(Topic::<T> {
+            name: topic.to_string(),
+            type_name: type_name.rsplit("::").next().unwrap(),
+            state: Mutex::new(TopicState {
+                publishers: Vec::new(),
+                subscribers: Vec::with_capacity(1),
+                meter: Meter::default(),
+                expected: None,
+                last: 0
+            }),
+            seq: AtomicUsize::new(0)
+        });
+
+        (Box::new(topic.clone()), topic)
+    });
+
+    entry.0.downcast_ref::<Arc<Topic<T>>>().unwrap().clone()
+}
+
+/// Returns the current time of the context's clock, monotonic by default.
+pub fn now() -> Duration {
+    clock::now()
+}
+
+/// Returns the name of the current node (the name of the thread).
+pub fn name() -> Arc<String> {
+    thread_local!(static NAME: Arc<String> = {
+        Arc::new(thread::current().name().unwrap_or("<unnamed>").to_string())
+    });
+
+    NAME.with(|name| name.clone())
+}
+
+pub struct Tick {
+    /// Actual time elapsed since the previous tick.
+    pub elapsed: Duration,
+    /// Whether the deadline was missed.
+    pub late: bool
+}
+
+impl Tick {
+    /// Returns the elapsed time in seconds.
+    pub fn dt(&self) -> f32 {
+        self.elapsed.as_secs() as f32 + self.elapsed.subsec_nanos() as f32 * 1e-9
+    }
+}
+
+/// Iterator over ticks locked to absolute deadlines, so the work time doesn't stretch the period.
+///
+/// Missed deadlines are skipped, counted and logged. The iteration ends on shutdown.
+pub struct Periodic {
+    period: u64,
+    deadline: u64,
+    last: u64,
+    overruns: usize,
+    reported: (u64, usize)
+}
+
+impl Periodic {
+    pub fn overruns(&self) -> usize {
+        self.overruns
+    }
+}
+
+impl Iterator for Periodic {
+    type Item = Tick;
+
+    fn next(&mut self) -> Option<Tick> {
+        let mut current = nanos(now());
+        let late = current > self.deadline;
+
+        if late {
+            self.overruns += 1;
+
+            // Don't flood the log by nodes, which are late all the time.
+            if current - self.reported.0 >= 1000000000 {
+                warn!("{} is late by {:.1}ms, {} overruns since the last report", name(),
+                      (current - self.deadline) as f32 * 1e-6, self.overruns - self.reported.1);
+                self.reported = (current, self.overruns);
+            }
+        } else {
+            let deadline = Duration::new(self.deadline / 1000000000,
+                                         (self.deadline % 1000000000) as u32);
+            clock::sleep_until(deadline);
+            current = nanos(now());
+        }
+
+        if shutdown::requested() {
+            return None;
+        }
+
+        // Skip missed deadlines to stay on the grid.
+        let missed = current.saturating_sub(self.deadline) / self.period;
+        self.deadline += (missed + 1) * self.period;
+
+        let elapsed = current - self.last;
+        self.last = current;
+
+        Some(Tick {
+            elapsed: Duration::new(elapsed / 1000000000, (elapsed % 1000000000) as u32),
+            late: late
+        })
+    }
+}
+
+pub fn periodic(rate: f32) -> Periodic {
+    let period = (1e9/rate).ceil() as u64;
+    let start = nanos(now());
+
+    Periodic {
+        period: period,
+        deadline: start + period,
+        last: start,
+        overruns: 0,
+        reported: (0, 0)
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1000000000 + duration.subsec_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use base::Error;
+    use base::harness::Harness;
+    use super::{call, serve, topics};
+
+    #[test]
+    fn call_answered() {
+        let harness = Harness::new();
+        let service = harness.enter(|| serve::<u32, u32>("double"));
+
+        thread::spawn(move || {
+            for request in service.iter() {
+                let resp = *request * 2;
+                request.reply(resp);
+            }
+        });
+
+        let resp = harness.enter(|| call::<u32, u32>("double", 21, Duration::from_secs(1)));
+        assert_eq!(resp.unwrap(), 42);
+    }
+
+    #[test]
+    fn call_unregistered() {
+        let harness = Harness::new();
+
+        match harness.enter(|| call::<u32, u32>("missing", 1, Duration::from_secs(1))) {
+            Err(Error::Other(message)) => assert_eq!(message, "no service \"missing\""),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn call_timeout() {
+        let harness = Harness::new();
+
+        // Keep the service registered, but never answer.
+        let _service = harness.enter(|| serve::<u32, u32>("silent"));
+
+        match harness.enter(|| call::<u32, u32>("silent", 1, Duration::from_millis(10))) {
+            Err(Error::Timeout(_)) => {},
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn dropped_input() {
+        let harness = Harness::new();
+        let output = harness.inject::<u32>("quiet");
+        let kept = harness.capture::<u32>("quiet");
+        drop(harness.capture::<u32>("quiet"));
+
+        // Nothing is published, but the dropped subscriber is forgotten anyway.
+        let info = harness.enter(topics).into_iter().find(|info| info.topic == "quiet").unwrap();
+        assert_eq!(info.subscribers.len(), 1);
+
+        output.send(7);
+        assert_eq!(kept.recv_timeout(Duration::from_secs(1)).unwrap().data, 7);
+    }
 }
@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::mem;
+use std::path::Path;
+use std::sync::Mutex;
+
+use base::{Error, Result};
+use base::config::{self, Document, Entry, Value};
+use base::node::{self, Input, Output, Payload};
+
+
+/// Type of a parameter. Changes are published on the topic named after the parameter.
+pub trait Param: Payload + Copy + PartialOrd {
+    fn to_value(self) -> Value;
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl Payload for f32 {}
+impl Payload for u32 {}
+impl Payload for bool {}
+
+impl Param for f32 {
+    fn to_value(self) -> Value {
+        Value::Num(self as f64)
+    }
+
+    fn from_value(value: &Value) -> Option<f32> {
+        match *value {
+            Value::Num(num) => Some(num as f32),
+            _ => None
+        }
+    }
+}
+
+impl Param for u32 {
+    fn to_value(self) -> Value {
+        Value::Num(self as f64)
+    }
+
+    fn from_value(value: &Value) -> Option<u32> {
+        match *value {
+            Value::Num(num) if num.fract() == 0. && 0. <= num && num <= u32::max_value() as f64 =>
+                Some(num as u32),
+            _ => None
+        }
+    }
+}
+
+impl Param for bool {
+    fn to_value(self) -> Value {
+        Value::Bool(self)
+    }
+
+    fn from_value(value: &Value) -> Option<bool> {
+        match *value {
+            Value::Bool(flag) => Some(flag),
+            _ => None
+        }
+    }
+}
+
+pub struct ParamInfo {
+    pub name: String,
+    pub value: Value,
+    pub default: Value,
+    pub range: Option<(Value, Value)>
+}
+
+struct Slot {
+    value: Value,
+    default: Value,
+    range: Option<(Value, Value)>,
+    /// Checks the value, returns it normalized.
+    check: Box<Fn(&Value) -> Option<Value> + Send>,
+    /// Publishes the checked value.
+    publish: Box<Fn(&Value) + Send>
+}
+
+/// Parameters of a context, see `node::Context`.
+pub struct Store(Mutex<StoreState>);
+
+struct StoreState {
+    slots: BTreeMap<String, Slot>,
+    /// Values from the file, which are taken by `declare` and kept until then.
+    saved: HashMap<String, Entry>
+}
+
+impl Store {
+    pub fn new() -> Store {
+        Store(Mutex::new(StoreState { slots: BTreeMap::new(), saved: HashMap::new() }))
+    }
+}
+
+/// Loads saved values, must be called before starting nodes. The file is optional.
+pub fn load() -> Result<()> {
+    let path = &config::get().params.path;
+
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+
+    let document = try!(Document::open(path));
+    let context = node::context();
+    let mut store = context.params.0.lock().unwrap();
+
+    for (key, entry) in document.entries() {
+        store.saved.insert(key.to_string(), entry.clone());
+    }
+
+    Ok(())
+}
+
+/// Declares the parameter within `[min, max]` and returns its current value and changes.
+///
+/// The saved value has priority over the default. The node can declare the parameter again after
+/// restart, then the current value is kept.
+pub fn declare<T: Param>(name: &str, default: T, min: T, max: T) -> (T, Input<T>) {
+    assert!(min <= default && default <= max);
+    add(name, default, Some((min, max)))
+}
+
+pub fn declare_flag(name: &str, default: bool) -> (bool, Input<bool>) {
+    add(name, default, None)
+}
+
+fn add<T: Param>(name: &str, default: T, range: Option<(T, T)>) -> (T, Input<T>) {
+    let context = node::context();
+    let mut store = context.params.0.lock().unwrap();
+
+    // Subscribe under the lock, so changes between reading and subscribing aren't lost.
+    let input = node::subscribe_to::<T>(name);
+
+    if let Some(slot) = store.slots.get(name) {
+        let value = T::from_value(&slot.value);
+        return (value.expect(&format!("{} is declared with another type", name)), input);
+    }
+
+    let output: Output<T> = node::advertise_as(name);
+
+    let value = match store.saved.remove(name) {
+        Some(entry) => match check(&entry.value, range) {
+            Some(value) => value,
+            None => {
+                warn!("{}:{}: invalid value {:?} of {}, using the default",
+                      entry.origin, entry.line, entry.value, name);
+                default
+            }
+        },
+        None => default
+    };
+
+    store.slots.insert(name.to_string(), Slot {
+        value: value.to_value(),
+        default: default.to_value(),
+        range: range.map(|(min, max)| (min.to_value(), max.to_value())),
+        check: Box::new(move |value| check(value, range).map(|value: T| value.to_value())),
+        publish: Box::new(move |value| output.send(T::from_value(value).unwrap()))
+    });
+
+    (value, input)
+}
+
+fn check<T: Param>(value: &Value, range: Option<(T, T)>) -> Option<T> {
+    T::from_value(value).and_then(|value| match range {
+        Some((min, max)) if value < min || max < value => None,
+        _ => Some(value)
+    })
+}
+
+/// Sets the parameter, saves the store and notifies subscribers.
+///
+/// The value is applied only if it's saved, so a failed call changes nothing.
+pub fn set(name: &str, value: &Value) -> Result<()> {
+    let context = node::context();
+    let mut store = context.params.0.lock().unwrap();
+
+    let previous = {
+        let slot = try!(store.slots.get_mut(name).ok_or_else(|| format!("unknown param {}", name)));
+
+        let value = try!(match slot.range {
+            Some((ref min, ref max)) => (slot.check)(value).ok_or_else(|| Error::Range {
+                name: name.to_string(),
+                value: value.to_string(),
+                min: min.to_string(),
+                max: max.to_string()
+            }),
+            None => (slot.check)(value).ok_or_else(|| {
+                Error::Other(format!("{} must be of the same type as {}, got {}",
+                                     name, slot.default, value))
+            })
+        });
+
+        mem::replace(&mut slot.value, value)
+    };
+
+    if let Err(err) = save(&store) {
+        store.slots.get_mut(name).unwrap().value = previous;
+        return Err(format!("cannot save params: {}", err).into());
+    }
+
+    let slot = &store.slots[name];
+    (slot.publish)(&slot.value);
+
+    info!("{} = {}", name, slot.value);
+    Ok(())
+}
+
+/// Returns the last value of the changes if any.
+pub fn latest<T: Copy>(input: &Input<T>) -> Option<T> {
+    let mut latest = None;
+
+    while let Ok(message) = input.try_recv() {
+        latest = Some(message.data);
+    }
+
+    latest
+}
+
+pub fn list() -> Vec<ParamInfo> {
+    let context = node::context();
+    let store = context.params.0.lock().unwrap();
+
+    store.slots.iter().map(|(name, slot)| ParamInfo {
+        name: name.clone(),
+        value: slot.value.clone(),
+        default: slot.default.clone(),
+        range: slot.range.clone()
+    }).collect()
+}
+
+// Only values different from defaults are saved, thus changed defaults in the config take effect.
+// Values of undeclared params (e.g. of nodes out of the profile) are kept as is.
+fn save(store: &StoreState) -> Result<()> {
+    let path = &config::get().params.path;
+    let tmp_path = format!("{}.tmp", path);
+
+    let mut lines = store.slots.iter()
+        .filter(|&(_, slot)| slot.value != slot.default)
+        .map(|(name, slot)| format!("{} = {}\n", name, slot.value))
+        .chain(store.saved.iter().map(|(name, entry)| format!("{} = {}\n", name, entry.value)))
+        .collect::<Vec<_>>();
+
+    lines.sort();
+
+    {
+        let mut file = try!(File::create(&tmp_path));
+        try!(file.write_all(b"# Changed parameters, written by hodok.\n"));
+        try!(file.write_all(lines.concat().as_bytes()));
+    }
+
+    // Don't lose the file if writing fails.
+    try!(fs::rename(&tmp_path, path));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use base::config::Value;
+    use base::harness::Harness;
+    use super::{declare, list};
+
+    #[test]
+    fn isolated() {
+        let first = Harness::new();
+        let second = Harness::new();
+
+        assert_eq!(first.enter(|| declare("test.gain", 1u32, 0, 10)).0, 1);
+        assert_eq!(second.enter(|| declare("test.gain", 2u32, 0, 10)).0, 2);
+
+        // Declared again after restart, the current value is kept.
+        assert_eq!(first.enter(|| declare("test.gain", 3u32, 0, 10)).0, 1);
+
+        let params = second.enter(list);
+        assert_eq!(params.len(), 1);
+        assert_eq!(params[0].value, Value::Num(2.));
+    }
+}
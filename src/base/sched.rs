@@ -0,0 +1,70 @@
+use std::io;
+use std::mem;
+
+use libc::{cpu_set_t, sched_param, CPU_SET, CPU_SETSIZE, PRIO_PROCESS, SCHED_FIFO};
+use libc::{pthread_self, pthread_setschedparam, sched_setaffinity, setpriority};
+
+use base::config::SchedConfig;
+
+
+/// Applies scheduling settings to the current thread.
+///
+/// Settings can be refused (e.g. real-time priorities require `CAP_SYS_NICE`), then they are
+/// logged and skipped: the node is better running with default scheduling than not running.
+pub fn apply(config: &SchedConfig) {
+    if let Some(ref cpus) = config.cpus {
+        match set_affinity(cpus) {
+            Ok(_) => info!("running on CPUs {:?}", cpus),
+            Err(err) => warn!("cannot set CPUs {:?}: {}", cpus, err)
+        }
+    }
+
+    if let Some(nice) = config.nice {
+        match set_nice(nice) {
+            Ok(_) => info!("running with nice {}", nice),
+            Err(err) => warn!("cannot set nice {}: {}", nice, err)
+        }
+    }
+
+    if let Some(priority) = config.priority {
+        match set_fifo(priority) {
+            Ok(_) => info!("running with SCHED_FIFO priority {}", priority),
+            Err(err) => warn!("cannot set SCHED_FIFO priority {}: {}", priority, err)
+        }
+    }
+}
+
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    let mut set: cpu_set_t = unsafe { mem::zeroed() };
+
+    for &cpu in cpus {
+        if cpu >= CPU_SETSIZE as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no such CPU"));
+        }
+
+        unsafe { CPU_SET(cpu, &mut set) };
+    }
+
+    // Zero means the calling thread.
+    match unsafe { sched_setaffinity(0, mem::size_of::<cpu_set_t>(), &set) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error())
+    }
+}
+
+fn set_nice(nice: i32) -> io::Result<()> {
+    // The nice value is per thread on Linux, zero means the calling thread.
+    match unsafe { setpriority(PRIO_PROCESS as _, 0, nice) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error())
+    }
+}
+
+fn set_fifo(priority: u32) -> io::Result<()> {
+    let param = sched_param { sched_priority: priority as i32 };
+
+    match unsafe { pthread_setschedparam(pthread_self(), SCHED_FIFO, &param) } {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno))
+    }
+}
@@ -0,0 +1,115 @@
+use std::mem;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use libc::{c_int, sigset_t, sigemptyset, sigaddset, sigwait, pthread_sigmask};
+use libc::{SIGINT, SIGTERM, SIGUSR1, SIG_BLOCK};
+
+use base::config;
+use base::logger;
+use base::node;
+
+
+/// Shutdown state of a context, see `node::Context`.
+pub struct State {
+    requested: AtomicBool,
+    watchers: Mutex<Vec<Sender<()>>>
+}
+
+impl State {
+    pub fn new() -> State {
+        State {
+            requested: AtomicBool::new(false),
+            watchers: Mutex::new(Vec::new())
+        }
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+
+        for tx in self.watchers.lock().unwrap().drain(..) {
+            let _ = tx.send(());
+        }
+    }
+
+    pub fn watch(&self) -> Receiver<()> {
+        let (tx, rx) = mpsc::channel();
+        let mut watchers = self.watchers.lock().unwrap();
+
+        if self.requested() {
+            let _ = tx.send(());
+        } else {
+            watchers.push(tx);
+        }
+
+        rx
+    }
+}
+
+/// Handles SIGINT and SIGTERM in a dedicated thread. Also logs topics on SIGUSR1.
+///
+/// Must be called before any other thread is spawned, because only new threads inherit the mask.
+pub fn install() {
+    let mut set: sigset_t = unsafe { mem::zeroed() };
+
+    unsafe {
+        sigemptyset(&mut set);
+        sigaddset(&mut set, SIGINT);
+        sigaddset(&mut set, SIGTERM);
+        sigaddset(&mut set, SIGUSR1);
+        assert_eq!(pthread_sigmask(SIG_BLOCK, &set, 0 as *mut sigset_t), 0);
+    }
+
+    thread::spawn(move || {
+        let mut signal: c_int = 0;
+
+        loop {
+            unsafe { sigwait(&set, &mut signal) };
+
+            if signal == SIGUSR1 {
+                node::log_topics();
+                continue;
+            }
+
+            if requested() {
+                warn!("got signal {} again, exiting immediately", signal);
+                logger::flush();
+                process::exit(1);
+            }
+
+            info!("got signal {}, shutting down", signal);
+            request();
+
+            thread::spawn(|| {
+                let timeout = config::get().shutdown.timeout;
+                thread::sleep(Duration::from_millis(timeout));
+                error!("nodes are still running after {}ms, exiting", timeout);
+                logger::flush();
+                process::exit(1);
+            });
+        }
+    });
+}
+
+pub fn requested() -> bool {
+    node::context().shutdown.requested()
+}
+
+pub fn request() {
+    node::context().shutdown.request();
+}
+
+/// Returns a receiver, which gets a message when the shutdown is requested.
+///
+/// Intended for nodes blocked in `select!`.
+pub fn watch() -> Receiver<()> {
+    node::context().shutdown.watch()
+}
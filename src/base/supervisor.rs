@@ -0,0 +1,157 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+use std::thread;
+use std::time::Duration;
+
+use base::config;
+use base::crash;
+use base::node::{self, Message, STACK_SIZE};
+use base::sched;
+use base::shutdown;
+use messages::{NodeState, NodeStatus};
+
+
+pub fn run(nodes: &[(&'static str, fn())]) {
+    let scale = config::get().clock.scale;
+
+    if scale != 1. {
+        info!("the clock runs x{}", scale);
+    }
+
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+
+    let monitors = nodes.iter().map(|&(name, worker)| {
+        let done_tx = done_tx.clone();
+
+        thread::Builder::new()
+            .name("supervisor".to_string())
+            .spawn(move || {
+                // Disconnects `done_rx` with the last monitor.
+                let _done_tx = done_tx;
+                supervise(name, worker);
+            }).unwrap()
+    }).collect::<Vec<_>>();
+
+    drop(done_tx);
+
+    // Meanwhile report panics of unsupervised threads (relays, listeners...).
+    loop {
+        let result = done_rx.recv_timeout(Duration::from_millis(100));
+        crash::report_unsupervised();
+
+        if result == Err(RecvTimeoutError::Disconnected) {
+            break;
+        }
+    }
+
+    for monitor in monitors {
+        let _ = monitor.join();
+    }
+}
+
+/// Returns the last known state of every supervised node.
+///
+/// States are published as `NodeState` too, but subscribers started after a node can miss them.
+pub fn states() -> Vec<Message<NodeState>> {
+    let source = Arc::new("supervisor".to_string());
+
+    get_states().lock().unwrap().iter().map(|(name, &(status, failures, stamp))| Message {
+        stamp: stamp,
+        seq: 0,
+        source: source.clone(),
+        data: NodeState { name: name.to_string(), status: status, failures: failures }
+    }).collect()
+}
+
+fn get_states() -> &'static Mutex<HashMap<&'static str, (NodeStatus, u32, Duration)>> {
+    type States = Mutex<HashMap<&'static str, (NodeStatus, u32, Duration)>>;
+    static mut STATES: *const States = 0 as *const States;
+    static ONCE: Once = ONCE_INIT;
+
+    ONCE.call_once(|| {
+        unsafe { STATES = mem::transmute(Box::new(States::new(HashMap::new()))) };
+    });
+
+    unsafe { &*STATES }
+}
+
+fn publish(name: &'static str, status: NodeStatus, failures: u32) {
+    let stamp = node::now();
+    get_states().lock().unwrap().insert(name, (status, failures, stamp));
+
+    let state = NodeState { name: name.to_string(), status: status, failures: failures };
+    node::advertise::<NodeState>().send_at(state, stamp);
+}
+
+fn supervise(name: &'static str, worker: fn()) {
+    let config = &config::get().supervisor;
+    let sched_config = config::get().sched.get(name);
+    let shutdown_rx = shutdown::watch();
+    let mut failures = 0;
+    let mut backoff = config.backoff;
+
+    loop {
+        info!("starting {}", name);
+        publish(name, NodeStatus::Running, failures);
+
+        let start = node::now();
+
+        let result = thread::Builder::new()
+            .name(name.to_string())
+            .stack_size(sched_config.map_or(STACK_SIZE, |config| config.stack_size))
+            .spawn(move || {
+                crash::supervised();
+
+                if let Some(config) = sched_config {
+                    sched::apply(config);
+                }
+
+                worker();
+            }).unwrap()
+            .join();
+
+        let payload = match result {
+            Ok(_) => {
+                info!("{} finished", name);
+                publish(name, NodeStatus::Stopped, failures);
+                break;
+            },
+            Err(payload) => payload
+        };
+
+        crash::report(name, &*payload);
+
+        // Forget old failures of a node, which has run stable for a while.
+        if node::now() - start > Duration::from_millis(config.max_backoff) {
+            failures = 0;
+            backoff = config.backoff;
+        }
+
+        failures += 1;
+
+        if shutdown::requested() {
+            publish(name, NodeStatus::Stopped, failures);
+            break;
+        }
+
+        if failures >= config.max_failures {
+            error!("{} failed {} times, giving up", name, failures);
+            publish(name, NodeStatus::Failed, failures);
+            break;
+        }
+
+        info!("restarting {} in {}ms", name, backoff);
+        publish(name, NodeStatus::Restarting, failures);
+
+        let timeout = Duration::from_millis(backoff);
+        backoff = cmp::min(backoff * 2, config.max_backoff);
+
+        if shutdown_rx.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout) {
+            publish(name, NodeStatus::Stopped, failures);
+            break;
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use base::node::{Input, Message, Payload};
+use base::shutdown;
+
+
+/// How many recent messages of every input are kept for matching.
+pub const QUEUE_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Match {
+    /// Stamps are equal, e.g. readings of one measurement.
+    Exact,
+    /// Stamps differ by no more than the tolerance, the closest messages are chosen.
+    Approximate(Duration)
+}
+
+/// Joins two inputs by stamps. Every message is used at most once, unmatched ones are dropped.
+pub fn pair<A: Payload, B: Payload>(a: Input<A>, b: Input<B>, mode: Match)
+    -> Receiver<(Arc<Message<A>>, Arc<Message<B>>)>
+{
+    let (tx, rx) = mpsc::channel();
+    let shutdown_rx = shutdown::watch();
+
+    thread::spawn(move || {
+        let mut matcher = Matcher::new(2, mode);
+
+        loop {
+            select! {
+                message = a.recv() => push(&mut matcher, 0, message.unwrap()),
+                message = b.recv() => push(&mut matcher, 1, message.unwrap()),
+                _ = shutdown_rx.recv() => break
+            }
+
+            while let Some(mut set) = matcher.pop() {
+                let second = take::<B>(&mut set);
+                let first = take::<A>(&mut set);
+
+                if tx.send((first, second)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+/// Joins three inputs by stamps, see `pair`.
+pub fn triple<A: Payload, B: Payload, C: Payload>(a: Input<A>, b: Input<B>, c: Input<C>,
+                                                  mode: Match)
+    -> Receiver<(Arc<Message<A>>, Arc<Message<B>>, Arc<Message<C>>)>
+{
+    let (tx, rx) = mpsc::channel();
+    let shutdown_rx = shutdown::watch();
+
+    thread::spawn(move || {
+        let mut matcher = Matcher::new(3, mode);
+
+        loop {
+            select! {
+                message = a.recv() => push(&mut matcher, 0, message.unwrap()),
+                message = b.recv() => push(&mut matcher, 1, message.unwrap()),
+                message = c.recv() => push(&mut matcher, 2, message.unwrap()),
+                _ = shutdown_rx.recv() => break
+            }
+
+            while let Some(mut set) = matcher.pop() {
+                let third = take::<C>(&mut set);
+                let second = take::<B>(&mut set);
+                let first = take::<A>(&mut set);
+
+                if tx.send((first, second, third)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+fn push<T: Payload>(matcher: &mut Matcher, index: usize, message: Arc<Message<T>>) {
+    matcher.push(index, message.stamp, Box::new(message));
+}
+
+fn take<T: Payload>(set: &mut Vec<Box<Any + Send>>) -> Arc<Message<T>> {
+    *set.pop().unwrap().downcast::<Arc<Message<T>>>().unwrap()
+}
+
+type Slot = (Duration, Box<Any + Send>);
+
+// Stamps within every input are assumed to increase. A set is formed around the pivot, which is
+// the latest of the oldest messages: no input can provide an earlier message for it anymore.
+// The set is final only if every other input has a message not earlier than the pivot, otherwise
+// a closer one can still arrive.
+struct Matcher {
+    tolerance: Duration,
+    queues: Vec<VecDeque<Slot>>
+}
+
+impl Matcher {
+    fn new(count: usize, mode: Match) -> Matcher {
+        Matcher {
+            tolerance: match mode {
+                Match::Exact => Duration::new(0, 0),
+                Match::Approximate(tolerance) => tolerance
+            },
+            queues: (0..count).map(|_| VecDeque::with_capacity(QUEUE_SIZE)).collect()
+        }
+    }
+
+    fn push(&mut self, index: usize, stamp: Duration, item: Box<Any + Send>) {
+        let queue = &mut self.queues[index];
+
+        if queue.back().map_or(false, |&(last, _)| stamp < last) {
+            warn!("skipping a message stamped earlier than the previous one");
+            return;
+        }
+
+        if queue.len() == QUEUE_SIZE {
+            queue.pop_front();
+        }
+
+        queue.push_back((stamp, item));
+    }
+
+    /// Returns the next matched set ordered by inputs.
+    fn pop(&mut self) -> Option<Vec<Box<Any + Send>>> {
+        loop {
+            if self.queues.iter().any(|queue| queue.is_empty()) {
+                return None;
+            }
+
+            let (pivot_index, pivot) = self.queues.iter()
+                .map(|queue| queue.front().unwrap().0)
+                .enumerate()
+                .max_by_key(|&(_, stamp)| stamp)
+                .unwrap();
+
+            if self.queues.iter().any(|queue| queue.back().unwrap().0 < pivot) {
+                return None;
+            }
+
+            let tolerance = self.tolerance;
+
+            let chosen = self.queues.iter().map(|queue| {
+                queue.iter().enumerate()
+                     .min_by_key(|&(_, &(stamp, _))| distance(stamp, pivot))
+                     .map(|(position, &(stamp, _))| (position, distance(stamp, pivot)))
+                     .unwrap()
+            }).collect::<Vec<_>>();
+
+            if chosen.iter().all(|&(_, distance)| distance <= tolerance) {
+                let set = self.queues.iter_mut().zip(chosen).map(|(queue, (position, _))| {
+                    // Older messages are worse candidates for later sets, drop them.
+                    queue.drain(..position);
+                    queue.pop_front().unwrap().1
+                }).collect();
+
+                return Some(set);
+            }
+
+            // Some input has no message close enough to the pivot and its later ones are even
+            // further, so the pivot can't be matched. Neither can messages, which are older than
+            // the pivot by more than the tolerance, because next pivots are later.
+            self.queues[pivot_index].pop_front();
+
+            for queue in &mut self.queues {
+                while queue.front().map_or(false, |&(stamp, _)| stamp + tolerance < pivot) {
+                    queue.pop_front();
+                }
+            }
+        }
+    }
+}
+
+fn distance(a: Duration, b: Duration) -> Duration {
+    if a > b { a - b } else { b - a }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::time::Duration;
+
+    use super::{Match, Matcher, QUEUE_SIZE};
+
+    fn push(matcher: &mut Matcher, index: usize, stamp: u64, id: u32) {
+        matcher.push(index, Duration::from_millis(stamp), Box::new(id));
+    }
+
+    fn pop(matcher: &mut Matcher) -> Option<Vec<u32>> {
+        matcher.pop().map(|set| {
+            set.into_iter().map(|item: Box<Any + Send>| *item.downcast::<u32>().unwrap()).collect()
+        })
+    }
+
+    #[test]
+    fn exact() {
+        let mut matcher = Matcher::new(3, Match::Exact);
+
+        push(&mut matcher, 0, 10, 1);
+        push(&mut matcher, 1, 10, 2);
+        assert_eq!(pop(&mut matcher), None);
+
+        push(&mut matcher, 2, 10, 3);
+        assert_eq!(pop(&mut matcher), Some(vec![1, 2, 3]));
+        assert_eq!(pop(&mut matcher), None);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut matcher = Matcher::new(2, Match::Exact);
+
+        push(&mut matcher, 0, 20, 1);
+        push(&mut matcher, 0, 10, 2);
+        push(&mut matcher, 1, 10, 3);
+
+        // The late message is skipped and the earlier one of the other input can't be matched.
+        assert_eq!(pop(&mut matcher), None);
+
+        push(&mut matcher, 1, 20, 4);
+        assert_eq!(pop(&mut matcher), Some(vec![1, 4]));
+        assert_eq!(pop(&mut matcher), None);
+    }
+
+    #[test]
+    fn tolerance_boundary() {
+        let mut matcher = Matcher::new(2, Match::Approximate(Duration::from_millis(5)));
+
+        push(&mut matcher, 0, 100, 1);
+        push(&mut matcher, 1, 105, 2);
+
+        // A closer message of the first input can still arrive.
+        assert_eq!(pop(&mut matcher), None);
+
+        push(&mut matcher, 0, 120, 3);
+        assert_eq!(pop(&mut matcher), Some(vec![1, 2]));
+
+        push(&mut matcher, 1, 126, 4);
+        push(&mut matcher, 0, 200, 5);
+
+        // 6ms is beyond the tolerance, so both messages are dropped.
+        assert_eq!(pop(&mut matcher), None);
+        assert!(matcher.queues.iter().all(|queue| queue.len() <= 1));
+
+        push(&mut matcher, 1, 200, 6);
+        assert_eq!(pop(&mut matcher), Some(vec![5, 6]));
+        assert_eq!(pop(&mut matcher), None);
+    }
+
+    #[test]
+    fn closest() {
+        let mut matcher = Matcher::new(2, Match::Approximate(Duration::from_millis(50)));
+
+        push(&mut matcher, 0, 100, 1);
+        push(&mut matcher, 1, 70, 2);
+        push(&mut matcher, 1, 95, 3);
+        push(&mut matcher, 1, 120, 4);
+
+        // Older candidates are dropped with the chosen one.
+        assert_eq!(pop(&mut matcher), Some(vec![1, 3]));
+
+        push(&mut matcher, 0, 200, 5);
+        push(&mut matcher, 1, 200, 6);
+        assert_eq!(pop(&mut matcher), Some(vec![5, 6]));
+    }
+
+    #[test]
+    fn eviction() {
+        let mut matcher = Matcher::new(2, Match::Approximate(Duration::from_secs(1)));
+
+        for i in 0..QUEUE_SIZE as u64 + 1 {
+            push(&mut matcher, 0, i, i as u32);
+        }
+
+        assert_eq!(matcher.queues[0].len(), QUEUE_SIZE);
+        assert_eq!(matcher.queues[0].front().unwrap().0, Duration::from_millis(1));
+
+        push(&mut matcher, 1, 1, 100);
+        assert_eq!(pop(&mut matcher), Some(vec![1, 100]));
+        assert_eq!(matcher.queues[0].len(), QUEUE_SIZE - 1);
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+use std::thread;
+use std::time::Duration;
+
+use base::config;
+use base::node::{self, Message};
+use messages::{Health, TopicHealth};
+
+
+/// Checks topics with the expected rate (see `Output::expect_rate`) until shutdown.
+///
+/// A topic, which is silent longer than `warn` periods, gets a warning, longer than `error`
+/// periods gets an error. Changes are logged and published as `TopicHealth`.
+pub fn start() {
+    thread::Builder::new()
+        .name("watchdog".to_string())
+        .spawn(watch).unwrap();
+}
+
+/// Returns the last known health of every checked topic.
+pub fn states() -> Vec<Message<TopicHealth>> {
+    let source = Arc::new("watchdog".to_string());
+
+    get_states().lock().unwrap().iter().map(|(topic, &(level, silence, stamp))| Message {
+        stamp: stamp,
+        seq: 0,
+        source: source.clone(),
+        data: TopicHealth { topic: topic.clone(), level: level, silence: silence }
+    }).collect()
+}
+
+fn get_states() -> &'static Mutex<HashMap<String, (Health, f32, Duration)>> {
+    type States = Mutex<HashMap<String, (Health, f32, Duration)>>;
+    static mut STATES: *const States = 0 as *const States;
+    static ONCE: Once = ONCE_INIT;
+
+    ONCE.call_once(|| {
+        unsafe { STATES = mem::transmute(Box::new(States::new(HashMap::new()))) };
+    });
+
+    unsafe { &*STATES }
+}
+
+fn watch() {
+    let config = &config::get().watchdog;
+    let health_tx = node::advertise::<TopicHealth>();
+
+    for _ in node::periodic(config.rate) {
+        for topic in node::topics() {
+            let expected = match topic.expected_rate {
+                Some(rate) => rate,
+                None => continue
+            };
+
+            let silence = topic.silence.as_secs() as f32 +
+                          topic.silence.subsec_nanos() as f32 * 1e-9;
+            let periods = silence * expected;
+
+            let level = if periods > config.error {
+                Health::Error
+            } else if periods > config.warn {
+                Health::Warning
+            } else {
+                Health::Ok
+            };
+
+            let id = topic.id();
+            let stamp = node::now();
+
+            let previous = get_states().lock().unwrap().insert(id.clone(), (level, silence, stamp));
+
+            if previous.map_or(Health::Ok, |(level, _, _)| level) == level {
+                continue;
+            }
+
+            match level {
+                Health::Ok => info!("{} resumed", id),
+                Health::Warning => warn!("{} is silent for {:.1}s at {}Hz", id, silence, expected),
+                Health::Error => error!("{} is silent for {:.1}s at {}Hz", id, silence, expected)
+            }
+
+            health_tx.send_at(TopicHealth { topic: id, level: level, silence: silence }, stamp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use base::harness::Harness;
+    use base::node::{Input, Message};
+    use messages::{Health, TopicHealth};
+
+    // Moves the clock until the watchdog reports a change of the level.
+    fn next(harness: &Harness, health: &Input<TopicHealth>, last: Health)
+        -> Arc<Message<TopicHealth>>
+    {
+        for _ in 0..100 {
+            harness.advance(Duration::from_millis(100));
+            harness.settle();
+
+            // The state is updated before the report is published.
+            let level = super::states().into_iter()
+                .find(|state| state.topic == "u32/probe")
+                .map_or(Health::Ok, |state| state.level);
+
+            if level != last {
+                return health.recv_timeout(Duration::from_secs(10)).unwrap();
+            }
+        }
+
+        panic!("no reports after 10s");
+    }
+
+    #[test]
+    fn silent_topic() {
+        let mut harness = Harness::new();
+        let health = harness.capture::<TopicHealth>("");
+        let probe = harness.inject::<u32>("probe");
+
+        harness.enter(|| probe.expect_rate(2.));
+        harness.spawn("watchdog", super::watch);
+        harness.settle();
+
+        // Checked twice a second: warned after 3 periods (1.5s), failed after 10 ones (5s).
+        let report = next(&harness, &health, Health::Ok);
+        assert_eq!((&report.topic[..], report.level), ("u32/probe", Health::Warning));
+        assert_eq!(report.stamp, Duration::from_secs(2));
+        assert_eq!(report.stamp, harness.now());
+
+        let report = next(&harness, &health, Health::Warning);
+        assert_eq!(report.level, Health::Error);
+        assert!(report.silence > 5.);
+
+        harness.enter(|| probe.send(1));
+
+        let report = next(&harness, &health, Health::Error);
+        assert_eq!(report.level, Health::Ok);
+
+        harness.stop().unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+use std::io;
+
+
+/// Explicit encoding of messages for sockets and recordings, independent of the struct layout
+/// and the host. All numbers are little-endian.
+pub trait Wire: Sized {
+    /// Version of the schema, must be bumped on any change of the encoding.
+    fn version() -> u8;
+    fn encode(&self, buf: &mut Vec<u8>);
+    fn decode(buf: &[u8]) -> io::Result<Self>;
+}
+
+/// Encodes the data prefixed by the version of the schema.
+pub fn encode<T: Wire>(data: &T, buf: &mut Vec<u8>) {
+    buf.push(T::version());
+    data.encode(buf);
+}
+
+/// Decodes the data encoded by `encode`, checking the version of the schema.
+pub fn decode<T: Wire>(buf: &[u8]) -> io::Result<T> {
+    match buf.first() {
+        Some(&version) if version == T::version() => T::decode(&buf[1..]),
+        Some(&version) => Err(invalid(format!("unsupported schema version {}", version))),
+        None => Err(invalid("empty message".to_string()))
+    }
+}
+
+pub fn read_string(buf: &[u8]) -> io::Result<String> {
+    String::from_utf8(buf.to_vec()).map_err(|err| invalid(err.to_string()))
+}
+
+pub fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{Wire, decode, encode, invalid};
+
+    #[derive(Debug, PartialEq)]
+    struct Byte(u8);
+
+    impl Wire for Byte {
+        fn version() -> u8 { 3 }
+
+        fn encode(&self, buf: &mut Vec<u8>) {
+            buf.push(self.0);
+        }
+
+        fn decode(buf: &[u8]) -> io::Result<Byte> {
+            buf.first().map(|&x| Byte(x)).ok_or_else(|| invalid("no byte".to_string()))
+        }
+    }
+
+    #[test]
+    fn versioned() {
+        let mut buf = Vec::new();
+        encode(&Byte(42), &mut buf);
+
+        assert_eq!(buf, [3, 42]);
+        assert_eq!(decode::<Byte>(&buf).unwrap(), Byte(42));
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let err = decode::<Byte>(&[4, 42]).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unsupported schema version 4");
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(decode::<Byte>(&[]).unwrap_err().to_string(), "empty message");
+        assert!(decode::<Byte>(&[3]).is_err());
+    }
+}
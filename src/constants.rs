@@ -1,14 +1,49 @@
+// Defaults, which can be overridden by the config file or `--set KEY=VALUE`.
+
 pub const AHRS_DEVICE: &'static str = "/dev/i2c-1";
 pub const AHRS_RATE: f32 = 25.;     // [Hz]
 pub const ACCEL_RANGE: f32 = 2.;    // [g]
 pub const MAGN_RANGE: f32 = 4.;     // [Gauss]
 pub const GYRO_RANGE: f32 = 250.;   // [°/s]
+pub const MADGWICK_BETA: f32 = 0.1;
 
 pub const PORT: u16 = 8000;
+pub const SERVER_ATTITUDE_RATE: f32 = 10.;  // [Hz]
+pub const SERVER_LOG_HISTORY: usize = 200;  // [records]
 
 pub const VIDEO_DEVICE: &'static str = "/dev/video0";
 pub const VIDEO_FPS: u32 = 20;
 pub const VIDEO_RESOLUTION: (u32, u32) = (640, 480);
 pub const VIDEO_GOF_SIZE: u32 = 120;
+pub const VIDEO_HFLIP: bool = true;
+pub const VIDEO_VFLIP: bool = true;
+pub const VIDEO_QUEUE_SIZE: usize = 10;  // [frames]
 
 pub const SYSINFO_RATE: f32 = 2.;   // [Hz]
+
+pub const RECORDER_PATH: &'static str = "hodok.rec";
+pub const RECORDER_KINDS: &'static [&'static str] = &["attitude", "sysinfo", "video"];
+pub const RECORDER_QUEUE: usize = 100;  // [messages]
+
+pub const PLAYER_PATH: &'static str = "hodok.rec";
+pub const PLAYER_REPEAT: bool = false;
+
+pub const SUPERVISOR_MAX_FAILURES: u32 = 5;
+pub const SUPERVISOR_BACKOFF: u64 = 500;        // [ms]
+pub const SUPERVISOR_MAX_BACKOFF: u64 = 30000;  // [ms]
+
+pub const SHUTDOWN_TIMEOUT: u64 = 3000;         // [ms]
+
+pub const CLOCK_SCALE: f64 = 1.;
+
+pub const LOG_LEVELS: &'static str = "debug";
+pub const LOG_MAX_SIZE: u64 = 1024;     // [KiB]
+pub const LOG_KEEP: u32 = 4;
+
+pub const WATCHDOG_RATE: f32 = 2.;      // [Hz]
+pub const WATCHDOG_WARN: f32 = 3.;      // [periods]
+pub const WATCHDOG_ERROR: f32 = 10.;    // [periods]
+
+pub const PARAMS_PATH: &'static str = "hodok.params";
+
+pub const CRASH_PATH: &'static str = "crashes";
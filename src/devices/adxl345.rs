@@ -26,9 +26,7 @@ impl Adxl345 {
     }
 
     fn identify(i2c: &I2C) -> Result<()> {
-        let mut check = [0];
-        try!(i2c.read(0x00, &mut check));
-        if check[0] != 0xe5 { Err(From::from("Unidentified device")) } else { Ok(()) }
+        i2c.identify(0x00, &[0xe5])
     }
 
     pub fn set_rate(&mut self, expected: f32) -> Result<f32> {
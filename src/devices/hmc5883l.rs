@@ -26,9 +26,7 @@ impl Hmc5883l {
     }
 
     fn identify(i2c: &I2C) -> Result<()> {
-        let mut check = [0, 0, 0];
-        try!(i2c.read(0x0a, &mut check));
-        if &check != b"H43" { Err(From::from("Unidentified device")) } else { Ok(()) }
+        i2c.identify(0x0a, b"H43")
     }
 
     pub fn set_rate(&mut self, expected: f32) -> Result<f32> {
@@ -26,9 +26,7 @@ impl L3g4200d {
     }
 
     fn identify(i2c: &I2C) -> Result<()> {
-        let mut check = [0];
-        try!(i2c.read(0x0f, &mut check));
-        if check[0] != 0xd3 { Err(From::from("Unidentified device")) } else { Ok(()) }
+        i2c.identify(0x0f, &[0xd3])
     }
 
     pub fn set_rate(&mut self, expected: f32) -> Result<f32> {
@@ -41,7 +39,9 @@ impl L3g4200d {
         self.buf[0] = 0x20;
         self.buf[1] = ctl;
 
+        // The control value also switches the device to the normal mode.
         try!(self.underline.write(&self.buf[0..2]));
+        self.running = true;
         Ok(actual)
     }
 
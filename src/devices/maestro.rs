@@ -1,5 +1,4 @@
-use std::io::Result;
-
+use base::Result;
 use ifaces::Serial;
 
 
@@ -13,6 +12,6 @@ impl Maestro {
     pub fn set_target(&self, channel: u8, mut us: u16) -> Result<()> {
         us <<= 2;
         let (lb, mb) = ((us & 0x7f) as u8, ((us >> 7) & 0x7f) as u8);
-        Ok(try!(self.0.write(&[0x84, channel, lb, mb])))
+        self.0.write(&[0x84, channel, lb, mb])
     }
 }
@@ -4,6 +4,9 @@ use std::mem;
 
 use libc::{open, write, read, close, O_RDWR, c_void, c_int, size_t};
 
+use base::{Error, Result};
+use ifaces::transferred;
+
 
 const I2C_SLAVE: c_int = 0x0703;
 
@@ -12,44 +15,90 @@ extern {
 }
 
 
-pub struct I2C(c_int);
+/// Device on the I2C bus. Errors carry the bus, the address and the register.
+pub struct I2C {
+    fd: c_int,
+    bus: String,
+    addr: u16
+}
 
 impl I2C {
-    pub fn open(bus: &str, addr: u16) -> io::Result<I2C> {
+    pub fn open(bus: &str, addr: u16) -> Result<I2C> {
         let c_str = CString::new(bus).unwrap();
+
         let fd = unsafe { open(c_str.as_ptr(), O_RDWR, 0) };
+        let cause = io::Error::last_os_error();
+        let i2c = I2C { fd: fd, bus: bus.to_string(), addr: addr };
 
-        check_io!(fd != -1);
-        check_io!(unsafe { ioctl(fd, I2C_SLAVE, addr as c_int) >= 0 });
+        if fd == -1 {
+            return Err(i2c.error(None, cause));
+        }
 
-        Ok(I2C(fd))
+        if unsafe { ioctl(fd, I2C_SLAVE, addr as c_int) } < 0 {
+            let cause = io::Error::last_os_error();
+            return Err(i2c.error(None, cause));
+        }
+
+        Ok(i2c)
     }
 
+    /// Writes the buffer starting with the register.
     #[inline]
-    pub fn write(&self, buf: &[u8]) -> io::Result<()> {
+    pub fn write(&self, buf: &[u8]) -> Result<()> {
         let bytes = unsafe {
-            write(self.0, buf.as_ptr() as *const c_void, buf.len() as size_t)
+            write(self.fd, buf.as_ptr() as *const c_void, buf.len() as size_t)
         };
 
-        check_io!(bytes as usize == buf.len());
-        Ok(())
+        transferred(bytes, buf.len(), io::ErrorKind::WriteZero)
+            .map_err(|cause| self.error(buf.first().cloned(), cause))
     }
 
     #[inline]
-    pub fn read(&self, reg: u8, buf: &mut [u8]) -> io::Result<()> {
-        check_io!(unsafe { write(self.0, mem::transmute(&reg), 1) == 1 });
+    pub fn read(&self, reg: u8, buf: &mut [u8]) -> Result<()> {
+        let bytes = unsafe { write(self.fd, mem::transmute(&reg), 1) };
+        try!(transferred(bytes, 1, io::ErrorKind::WriteZero)
+                 .map_err(|cause| self.error(Some(reg), cause)));
 
         let bytes = unsafe {
-            read(self.0, buf.as_mut_ptr() as *mut c_void, buf.len() as size_t)
+            read(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len() as size_t)
         };
 
-        check_io!(bytes as usize == buf.len());
+        transferred(bytes, buf.len(), io::ErrorKind::UnexpectedEof)
+            .map_err(|cause| self.error(Some(reg), cause))
+    }
+
+    /// Checks that the register (usually "WHO_AM_I") contains the expected identity.
+    pub fn identify(&self, reg: u8, expected: &[u8]) -> Result<()> {
+        let mut got = vec![0; expected.len()];
+        try!(self.read(reg, &mut got));
+
+        if &got[..] != expected {
+            return Err(Error::Identify {
+                bus: self.bus.clone(),
+                addr: self.addr,
+                reg: reg,
+                expected: expected.to_vec(),
+                got: got
+            });
+        }
+
         Ok(())
     }
+
+    fn error(&self, reg: Option<u8>, cause: io::Error) -> Error {
+        Error::Bus {
+            bus: self.bus.clone(),
+            addr: self.addr,
+            reg: reg,
+            cause: cause
+        }
+    }
 }
 
 impl Drop for I2C {
     fn drop(&mut self) {
-        unsafe { close(self.0); }
+        if self.fd != -1 {
+            unsafe { close(self.fd); }
+        }
     }
 }
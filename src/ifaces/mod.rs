@@ -1,11 +1,21 @@
+use std::io;
+
+use libc::ssize_t;
+
 pub use self::i2c::I2C;
 pub use self::serial::Serial;
 
 
-macro_rules! check_io(
-    ($cond:expr) =>
-        (if !$cond { return Err(io::Error::last_os_error()); })
-);
-
 pub mod i2c;
 pub mod serial;
+
+// Must be called right after the transfer, before anything (e.g. an allocation) changes errno.
+fn transferred(bytes: ssize_t, expected: usize, short: io::ErrorKind) -> io::Result<()> {
+    if bytes < 0 {
+        Err(io::Error::last_os_error())
+    } else if bytes as usize != expected {
+        Err(io::Error::new(short, format!("transferred {} of {} bytes", bytes, expected)))
+    } else {
+        Ok(())
+    }
+}
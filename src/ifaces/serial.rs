@@ -4,6 +4,9 @@ use std::io;
 use libc::{open, write, read, close, O_RDWR, O_NOCTTY};
 use libc::{c_void, c_uchar, c_int, c_uint, size_t};
 
+use base::{Error, Result};
+use ifaces::transferred;
+
 
 #[repr(C)]
 struct Termios {
@@ -34,13 +37,23 @@ extern {
 }
 
 
-pub struct Serial(c_int);
+/// Serial port in the raw mode. Errors carry the path of the device.
+pub struct Serial {
+    fd: c_int,
+    device: String
+}
 
 impl Serial {
-    pub fn open(device: &str) -> io::Result<Serial> {
+    pub fn open(device: &str) -> Result<Serial> {
         let c_str = CString::new(device).unwrap();
+
         let fd = unsafe { open(c_str.as_ptr(), O_RDWR|O_NOCTTY, 0) };
-        check_io!(fd != -1);
+        let cause = io::Error::last_os_error();
+        let serial = Serial { fd: fd, device: device.to_string() };
+
+        if fd == -1 {
+            return Err(serial.error(cause));
+        }
 
         let mut options = Termios {
             c_iflag: 0,
@@ -53,39 +66,50 @@ impl Serial {
             c_ospeed: 0
         };
 
-        unsafe {
-            check_io!(tcgetattr(fd, &mut options) == 0);
-            options.c_lflag &= !(ECHO|ECHONL|ICANON|ISIG|IEXTEN);
-            options.c_oflag &= !(ONLCR|OCRNL);
-            check_io!(tcsetattr(fd, TCSANOW, &mut options) == 0);
+        if unsafe { tcgetattr(fd, &mut options) } != 0 {
+            let cause = io::Error::last_os_error();
+            return Err(serial.error(cause));
         }
 
-        Ok(Serial(fd))
+        options.c_lflag &= !(ECHO|ECHONL|ICANON|ISIG|IEXTEN);
+        options.c_oflag &= !(ONLCR|OCRNL);
+
+        if unsafe { tcsetattr(fd, TCSANOW, &mut options) } != 0 {
+            let cause = io::Error::last_os_error();
+            return Err(serial.error(cause));
+        }
+
+        Ok(serial)
     }
 
     #[inline]
-    pub fn write(&self, buf: &[u8]) -> io::Result<()> {
+    pub fn write(&self, buf: &[u8]) -> Result<()> {
         let bytes = unsafe {
-            write(self.0, buf.as_ptr() as *const c_void, buf.len() as size_t)
+            write(self.fd, buf.as_ptr() as *const c_void, buf.len() as size_t)
         };
 
-        check_io!(bytes as usize == buf.len());
-        Ok(())
+        transferred(bytes, buf.len(), io::ErrorKind::WriteZero).map_err(|cause| self.error(cause))
     }
 
     #[inline]
-    pub fn read(&self, buf: &mut [u8]) -> io::Result<()> {
+    pub fn read(&self, buf: &mut [u8]) -> Result<()> {
         let bytes = unsafe {
-            read(self.0, buf.as_mut_ptr() as *mut c_void, buf.len() as size_t)
+            read(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len() as size_t)
         };
 
-        check_io!(bytes as usize == buf.len());
-        Ok(())
+        transferred(bytes, buf.len(), io::ErrorKind::UnexpectedEof)
+            .map_err(|cause| self.error(cause))
+    }
+
+    fn error(&self, cause: io::Error) -> Error {
+        Error::Io(io::Error::new(cause.kind(), format!("{}: {}", self.device, cause)))
     }
 }
 
 impl Drop for Serial {
     fn drop(&mut self) {
-        unsafe { close(self.0); }
+        if self.fd != -1 {
+            unsafe { close(self.fd); }
+        }
     }
 }
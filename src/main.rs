@@ -1,8 +1,10 @@
 #![allow(dead_code)]
 #![feature(mpsc_select)]
+#![feature(core_intrinsics)]
 
 #[macro_use]
 extern crate log;
+extern crate backtrace;
 extern crate byteorder;
 extern crate httparse;
 extern crate libc;
@@ -18,14 +20,42 @@ mod ifaces;
 mod messages;
 mod nodes;
 
+use std::env;
+use std::process;
+
 
 fn main() {
     base::logger::init().unwrap();
 
-    run_nodes![
-        ahrs
-        server
-        sysinfo
-        video
-    ];
+    let config = base::config::load(env::args().skip(1)).unwrap_or_else(|err| {
+        error!("{}", err);
+        process::exit(2);
+    });
+
+    base::config::set(config);
+    base::crash::install();
+
+    base::logger::configure(&base::config::get().log).unwrap_or_else(|err| {
+        error!("{}", err);
+        process::exit(2);
+    });
+
+    base::params::load().unwrap_or_else(|err| {
+        error!("{}", err);
+        base::logger::flush();
+        process::exit(2);
+    });
+
+    let nodes = nodes::select(base::config::get()).unwrap_or_else(|err| {
+        error!("{}", err);
+        base::logger::flush();
+        process::exit(2);
+    });
+
+    base::shutdown::install();
+    base::watchdog::start();
+    base::supervisor::run(&nodes);
+
+    info!("all nodes are stopped");
+    base::logger::flush();
 }
@@ -1,7 +1,75 @@
-pub use rscam::Frame as VideoFrame;
+use std::cmp;
+use std::io;
+use std::ops::Deref;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use log::LogLevel;
+
+use base::node::Payload;
+use base::wire::{self, Wire};
+
+
+/// Encoded (H.264) frame.
+///
+/// Owns the data, so camera buffers are returned to the driver immediately and frames can be
+/// produced without a camera (e.g. by the player).
+pub struct VideoFrame(pub Vec<u8>);
+
+impl Payload for VideoFrame {
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl Deref for VideoFrame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// Layout: the frame as is.
+impl Wire for VideoFrame {
+    fn version() -> u8 { 1 }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend(&self.0);
+    }
+
+    fn decode(buf: &[u8]) -> io::Result<VideoFrame> {
+        Ok(VideoFrame(buf.to_vec()))
+    }
+}
+
+/// Request of the `video.flip` service, which toggles the flip of the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flip {
+    Horizontal,
+    Vertical
+}
 
 pub struct Attitude(pub f32, pub f32, pub f32, pub f32);
 
+impl Payload for Attitude {}
+
+// Layout: w, x, y, z (f32).
+impl Wire for Attitude {
+    fn version() -> u8 { 1 }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.write_f32::<LittleEndian>(self.0).unwrap();
+        buf.write_f32::<LittleEndian>(self.1).unwrap();
+        buf.write_f32::<LittleEndian>(self.2).unwrap();
+        buf.write_f32::<LittleEndian>(self.3).unwrap();
+    }
+
+    fn decode(mut buf: &[u8]) -> io::Result<Attitude> {
+        Ok(Attitude(try!(buf.read_f32::<LittleEndian>()), try!(buf.read_f32::<LittleEndian>()),
+                    try!(buf.read_f32::<LittleEndian>()), try!(buf.read_f32::<LittleEndian>())))
+    }
+}
+
 pub struct SysInfo {
     pub free_mem: u8,
     pub avail_mem: u8,
@@ -9,3 +77,294 @@ pub struct SysInfo {
     pub loadavg: (u8, u8, u8),
     pub temp: i8
 }
+
+impl Payload for SysInfo {}
+
+// Layout: free_mem, avail_mem, cpu, loadavg (u8 × 3), temp (i8).
+impl Wire for SysInfo {
+    fn version() -> u8 { 1 }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend(&[self.free_mem, self.avail_mem, self.cpu,
+                     self.loadavg.0, self.loadavg.1, self.loadavg.2, self.temp as u8]);
+    }
+
+    fn decode(buf: &[u8]) -> io::Result<SysInfo> {
+        if buf.len() < 7 {
+            return Err(wire::invalid("truncated SysInfo".to_string()));
+        }
+
+        Ok(SysInfo {
+            free_mem: buf[0],
+            avail_mem: buf[1],
+            cpu: buf[2],
+            loadavg: (buf[3], buf[4], buf[5]),
+            temp: buf[6] as i8
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Health {
+    Ok = 0,
+    Warning = 1,
+    Error = 2
+}
+
+/// Reported by the watchdog on changes of the health of the topic.
+pub struct TopicHealth {
+    /// The topic as `Type/topic` or `Type`.
+    pub topic: String,
+    pub level: Health,
+    pub silence: f32    // [s]
+}
+
+impl Payload for TopicHealth {}
+
+// Layout: level (u8), silence (f32), topic (the rest, UTF-8).
+impl Wire for TopicHealth {
+    fn version() -> u8 { 1 }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.write_u8(self.level as u8).unwrap();
+        buf.write_f32::<LittleEndian>(self.silence).unwrap();
+        buf.extend(self.topic.as_bytes());
+    }
+
+    fn decode(mut buf: &[u8]) -> io::Result<TopicHealth> {
+        let level = match try!(buf.read_u8()) {
+            0 => Health::Ok,
+            1 => Health::Warning,
+            2 => Health::Error,
+            level => return Err(wire::invalid(format!("unknown health {}", level)))
+        };
+
+        let silence = try!(buf.read_f32::<LittleEndian>());
+        let topic = try!(wire::read_string(buf));
+
+        Ok(TopicHealth { topic: topic, level: level, silence: silence })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeStatus {
+    Running = 0,
+    Restarting = 1,
+    Stopped = 2,
+    Failed = 3
+}
+
+pub struct NodeState {
+    pub name: String,
+    pub status: NodeStatus,
+    pub failures: u32
+}
+
+impl Payload for NodeState {}
+
+// Layout: status (u8), failures (u32), name (the rest, UTF-8).
+impl Wire for NodeState {
+    fn version() -> u8 { 1 }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.write_u8(self.status as u8).unwrap();
+        buf.write_u32::<LittleEndian>(self.failures).unwrap();
+        buf.extend(self.name.as_bytes());
+    }
+
+    fn decode(mut buf: &[u8]) -> io::Result<NodeState> {
+        let status = match try!(buf.read_u8()) {
+            0 => NodeStatus::Running,
+            1 => NodeStatus::Restarting,
+            2 => NodeStatus::Stopped,
+            3 => NodeStatus::Failed,
+            status => return Err(wire::invalid(format!("unknown node status {}", status)))
+        };
+
+        let failures = try!(buf.read_u32::<LittleEndian>());
+        let name = try!(wire::read_string(buf));
+
+        Ok(NodeState { name: name, status: status, failures: failures })
+    }
+}
+
+/// Published by the supervisor when a thread panics, see `base::crash`.
+pub struct Fault {
+    /// The node (the name of the thread).
+    pub node: String,
+    pub message: String,
+    /// Where the panic happened as `file:line`.
+    pub location: String,
+    /// Path of the crash report if it's written.
+    pub report: Option<String>
+}
+
+impl Payload for Fault {}
+
+/// Published by the logger for every record, see `base::logger`.
+pub struct LogEntry {
+    pub level: LogLevel,
+    /// The node (the name of the thread) or the module for unnamed threads.
+    pub node: String,
+    pub text: String
+}
+
+impl Payload for LogEntry {
+    fn size(&self) -> usize {
+        self.node.len() + self.text.len()
+    }
+}
+
+// Layout: level (u8, 1 is error ... 5 is trace), node's length (u8), node, text (the rest, UTF-8).
+impl Wire for LogEntry {
+    fn version() -> u8 { 1 }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let node = &self.node.as_bytes()[..cmp::min(self.node.len(), 255)];
+
+        buf.write_u8(self.level as u8).unwrap();
+        buf.write_u8(node.len() as u8).unwrap();
+        buf.extend(node);
+        buf.extend(self.text.as_bytes());
+    }
+
+    fn decode(mut buf: &[u8]) -> io::Result<LogEntry> {
+        let level = match try!(buf.read_u8()) {
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            3 => LogLevel::Info,
+            4 => LogLevel::Debug,
+            5 => LogLevel::Trace,
+            level => return Err(wire::invalid(format!("unknown log level {}", level)))
+        };
+
+        let length = try!(buf.read_u8()) as usize;
+
+        if buf.len() < length {
+            return Err(wire::invalid("truncated node".to_string()));
+        }
+
+        let node = try!(wire::read_string(&buf[..length]));
+        let text = try!(wire::read_string(&buf[length..]));
+
+        Ok(LogEntry { level: level, node: node, text: text })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::LogLevel;
+
+    use base::wire::{self, Wire};
+    use super::*;
+
+    fn encode<T: Wire>(data: &T) -> Vec<u8> {
+        let mut buf = Vec::new();
+        wire::encode(data, &mut buf);
+        buf
+    }
+
+    // Decoding must fail on any cut of the fixed part and on an unknown version.
+    fn check_truncated<T: Wire>(buf: &[u8], fixed: usize) {
+        for length in 0..fixed {
+            assert!(wire::decode::<T>(&buf[..length]).is_err(), "decoded {} bytes", length);
+        }
+
+        let mut other = buf.to_vec();
+        other[0] = T::version() + 1;
+        assert!(wire::decode::<T>(&other).is_err());
+    }
+
+    #[test]
+    fn attitude() {
+        let buf = encode(&Attitude(1., -0.5, 0.25, 0.));
+
+        assert_eq!(buf.len(), 1 + 16);
+        assert_eq!(&buf[1..5], &[0, 0, 0x80, 0x3f]);
+
+        let Attitude(w, x, y, z) = wire::decode(&buf).unwrap();
+        assert_eq!((w, x, y, z), (1., -0.5, 0.25, 0.));
+
+        check_truncated::<Attitude>(&buf, buf.len());
+    }
+
+    #[test]
+    fn sysinfo() {
+        let buf = encode(&SysInfo {
+            free_mem: 10,
+            avail_mem: 20,
+            cpu: 30,
+            loadavg: (1, 2, 3),
+            temp: -15
+        });
+
+        assert_eq!(buf.len(), 1 + 7);
+
+        let info = wire::decode::<SysInfo>(&buf).unwrap();
+        assert_eq!((info.free_mem, info.avail_mem, info.cpu), (10, 20, 30));
+        assert_eq!(info.loadavg, (1, 2, 3));
+        assert_eq!(info.temp, -15);
+
+        check_truncated::<SysInfo>(&buf, buf.len());
+    }
+
+    #[test]
+    fn node_state() {
+        let buf = encode(&NodeState {
+            name: "video".to_string(),
+            status: NodeStatus::Failed,
+            failures: 5
+        });
+
+        let state = wire::decode::<NodeState>(&buf).unwrap();
+        assert_eq!(state.name, "video");
+        assert_eq!(state.status, NodeStatus::Failed);
+        assert_eq!(state.failures, 5);
+
+        check_truncated::<NodeState>(&buf, 1 + 5);
+
+        let mut unknown = buf.clone();
+        unknown[1] = 4;
+        assert!(wire::decode::<NodeState>(&unknown).is_err());
+    }
+
+    #[test]
+    fn topic_health() {
+        let buf = encode(&TopicHealth {
+            topic: "VideoFrame/rear".to_string(),
+            level: Health::Warning,
+            silence: 1.5
+        });
+
+        let health = wire::decode::<TopicHealth>(&buf).unwrap();
+        assert_eq!(health.topic, "VideoFrame/rear");
+        assert_eq!(health.level, Health::Warning);
+        assert_eq!(health.silence, 1.5);
+
+        check_truncated::<TopicHealth>(&buf, 1 + 5);
+
+        let mut invalid = buf.clone();
+        invalid[6] = 0xff;
+        assert!(wire::decode::<TopicHealth>(&invalid).is_err());
+    }
+
+    #[test]
+    fn log_entry() {
+        let buf = encode(&LogEntry {
+            level: LogLevel::Warn,
+            node: "ahrs".to_string(),
+            text: "ярко".to_string()
+        });
+
+        let entry = wire::decode::<LogEntry>(&buf).unwrap();
+        assert_eq!(entry.level, LogLevel::Warn);
+        assert_eq!(entry.node, "ahrs");
+        assert_eq!(entry.text, "ярко");
+
+        check_truncated::<LogEntry>(&buf, 1 + 2 + 4);
+
+        let mut unknown = buf.clone();
+        unknown[1] = 0;
+        assert!(wire::decode::<LogEntry>(&unknown).is_err());
+    }
+}
@@ -16,6 +16,11 @@ impl Madgwick {
         }
     }
 
+    pub fn set_beta(&mut self, beta: f32) {
+        assert!(beta > 0.);
+        self.beta = beta;
+    }
+
     pub fn update(&mut self,
                   (gx, gy, gz): (f32, f32, f32),                // [rad/s]
                   (mut ax, mut ay, mut az): (f32, f32, f32),    // [g]
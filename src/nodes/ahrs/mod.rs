@@ -1,8 +1,8 @@
 use std::f32::consts::PI;
-use std::mem;
 
+use base::config;
 use base::node;
-use constants::{AHRS_DEVICE, AHRS_RATE, ACCEL_RANGE, MAGN_RANGE, GYRO_RANGE};
+use base::params;
 use devices::Adxl345;
 use devices::Hmc5883l;
 use devices::L3g4200d;
@@ -16,32 +16,84 @@ mod madgwick;
 pub fn worker() {
     let attitude_tx = node::advertise::<Attitude>();
 
-    let mut accel = Adxl345::new(AHRS_DEVICE).unwrap();
-    let accel_rate = accel.set_rate(AHRS_RATE).unwrap();
-    let accel_range = accel.set_range(ACCEL_RANGE).unwrap();
+    let config = &config::get().ahrs;
+
+    let (rate, rate_rx) = params::declare("ahrs.rate", config.rate, 0.1, 3200.);
+    let (accel_range, accel_range_rx) =
+        params::declare("ahrs.accel_range", config.accel_range, 2., 16.);
+    let (magn_range, magn_range_rx) =
+        params::declare("ahrs.magn_range", config.magn_range, 0.88, 8.1);
+    let (gyro_range, gyro_range_rx) =
+        params::declare("ahrs.gyro_range", config.gyro_range, 250., 2000.);
+    let (beta, beta_rx) = params::declare("ahrs.beta", config.beta, 0.001, 10.);
+
+    let mut accel = Adxl345::new(&config.device).unwrap();
+    let accel_rate = accel.set_rate(rate).unwrap();
+    let accel_range = accel.set_range(accel_range).unwrap();
 
     info!("accelerometer: {}Hz, ±{}g", accel_rate, accel_range);
 
-    let mut magn = Hmc5883l::new(AHRS_DEVICE).unwrap();
-    let magn_rate = magn.set_rate(AHRS_RATE).unwrap();
-    let magn_range = magn.set_range(MAGN_RANGE).unwrap();
+    let mut magn = Hmc5883l::new(&config.device).unwrap();
+    let magn_rate = magn.set_rate(rate).unwrap();
+    let magn_range = magn.set_range(magn_range).unwrap();
 
     info!("magnetometer: {}Hz, ±{}Gauss", magn_rate, magn_range);
 
-    let mut gyro = L3g4200d::new(AHRS_DEVICE).unwrap();
-    let gyro_rate = gyro.set_rate(AHRS_RATE).unwrap();
-    let gyro_range = gyro.set_range(GYRO_RANGE).unwrap();
+    let mut gyro = L3g4200d::new(&config.device).unwrap();
+    let gyro_rate = gyro.set_rate(rate).unwrap();
+    let gyro_range = gyro.set_range(gyro_range).unwrap();
 
     info!("gyroscope: {}Hz, ±{}°/s", gyro_rate, gyro_range);
 
-    let mut filter = Madgwick::new();
+    let mut filter = Madgwick::with_beta(beta);
 
     accel.start().unwrap();
     magn.start().unwrap();
 
-    info!("running at {}Hz", AHRS_RATE);
-
-    for _ in node::periodic(AHRS_RATE) {
+    info!("running at {}Hz", rate);
+
+    attitude_tx.expect_rate(rate);
+    let mut ticks = node::periodic(rate);
+
+    while let Some(tick) = ticks.next() {
+        // Apply changed parameters. Failures aren't fatal: the device keeps the previous setting.
+        if let Some(rate) = params::latest(&rate_rx) {
+            match (accel.set_rate(rate), magn.set_rate(rate), gyro.set_rate(rate)) {
+                (Ok(_), Ok(_), Ok(_)) => info!("running at {}Hz", rate),
+                _ => warn!("cannot set the rate to {}Hz", rate)
+            }
+
+            attitude_tx.expect_rate(rate);
+            ticks = node::periodic(rate);
+            continue;
+        }
+
+        if let Some(range) = params::latest(&accel_range_rx) {
+            match accel.set_range(range) {
+                Ok(range) => info!("accelerometer: ±{}g", range),
+                Err(err) => warn!("cannot set the accelerometer range: {}", err)
+            }
+        }
+
+        if let Some(range) = params::latest(&magn_range_rx) {
+            match magn.set_range(range) {
+                Ok(range) => info!("magnetometer: ±{}Gauss", range),
+                Err(err) => warn!("cannot set the magnetometer range: {}", err)
+            }
+        }
+
+        if let Some(range) = params::latest(&gyro_range_rx) {
+            match gyro.set_range(range) {
+                Ok(range) => info!("gyroscope: ±{}°/s", range),
+                Err(err) => warn!("cannot set the gyroscope range: {}", err)
+            }
+        }
+
+        if let Some(beta) = params::latest(&beta_rx) {
+            filter.set_beta(beta);
+        }
+
+        let stamp = node::now();
         let (gx, gy, gz) = gyro.measure().unwrap();
 
         const DEG_TO_RAD: f32 = PI / 180.;
@@ -49,11 +101,9 @@ pub fn worker() {
         let a = accel.measure().unwrap();
         let m = magn.measure().unwrap();
 
-        let q = filter.update(g, a, m, AHRS_RATE.recip());
+        let q = filter.update(g, a, m, tick.dt());
 
         // Transform the frame.
-        let q = (q.0, -q.1, q.2, -q.3);
-
-        attitude_tx.send(unsafe { mem::transmute(q) });
+        attitude_tx.send_at(Attitude(q.0, -q.1, q.2, -q.3), stamp);
     }
 }
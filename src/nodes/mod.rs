@@ -1,4 +1,78 @@
+use base::Result;
+use base::config::Config;
+
 pub mod ahrs;
+pub mod player;
+pub mod recorder;
 pub mod server;
 pub mod sysinfo;
 pub mod video;
+
+
+pub static NODES: &'static [(&'static str, fn())] = &[
+    ("ahrs", ahrs::worker as fn()),
+    ("player", player::worker as fn()),
+    ("recorder", recorder::worker as fn()),
+    ("server", server::worker as fn()),
+    ("sysinfo", sysinfo::worker as fn()),
+    ("video", video::worker as fn())
+];
+
+pub static PROFILES: &'static [(&'static str, &'static [&'static str])] = &[
+    ("full", &["ahrs", "server", "sysinfo", "video"]),
+    // No camera on the bench.
+    ("bench", &["ahrs", "server", "sysinfo"]),
+    // No hardware at all, data comes from the recording.
+    ("sim", &["player", "server", "sysinfo"])
+];
+
+/// Resolves the nodes to run: either listed explicitly or taken from the profile.
+pub fn select(config: &Config) -> Result<Vec<(&'static str, fn())>> {
+    let names = match config.nodes {
+        Some(ref nodes) => nodes.clone(),
+        None => try!(profile(config))
+    };
+
+    let mut nodes = Vec::with_capacity(names.len());
+
+    for name in names {
+        match NODES.iter().find(|&&(node, _)| node == name) {
+            Some(&node) if !nodes.contains(&node) => nodes.push(node),
+            Some(_) => {},
+            None => return Err(From::from(format!("unknown node \"{}\", expected one of: {}",
+                                                  name, list(NODES.iter().map(|x| x.0)))))
+        }
+    }
+
+    if nodes.is_empty() {
+        return Err(From::from("no nodes to run"));
+    }
+
+    for name in config.sched.keys() {
+        if !NODES.iter().any(|&(node, _)| node == name) {
+            return Err(From::from(format!("unknown node \"{}\" in [sched.{}], expected one of: {}",
+                                          name, name, list(NODES.iter().map(|x| x.0)))));
+        }
+    }
+
+    Ok(nodes)
+}
+
+fn profile(config: &Config) -> Result<Vec<String>> {
+    if let Some(nodes) = config.profiles.get(&config.profile) {
+        return Ok(nodes.clone());
+    }
+
+    match PROFILES.iter().find(|&&(profile, _)| profile == config.profile) {
+        Some(&(_, nodes)) => Ok(nodes.iter().map(|x| x.to_string()).collect()),
+        None => {
+            let names = PROFILES.iter().map(|x| x.0).chain(config.profiles.keys().map(|x| &x[..]));
+            Err(From::from(format!("unknown profile \"{}\", expected one of: {}",
+                                   config.profile, list(names))))
+        }
+    }
+}
+
+fn list<'a, I: Iterator<Item=&'a str>>(names: I) -> String {
+    names.collect::<Vec<_>>().join(", ")
+}
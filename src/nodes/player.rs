@@ -0,0 +1,94 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::time::Duration;
+
+use base::clock;
+use base::config;
+use base::node;
+use base::shutdown;
+use nodes::recorder::{self, KINDS};
+
+
+pub fn worker() {
+    let config = &config::get().player;
+
+    info!("playing {}", config.path);
+
+    loop {
+        // Restarts can't fix a missing or broken file.
+        if let Err(err) = play(&config.path) {
+            error!("can't play {}: {}", config.path, err);
+            break;
+        }
+
+        if !config.repeat || shutdown::requested() {
+            break;
+        }
+
+        info!("repeating {}", config.path);
+    }
+}
+
+fn play(path: &str) -> io::Result<()> {
+    let mut file = BufReader::new(try!(File::open(path)));
+    try!(recorder::read_header(&mut file));
+
+    // About 100ms of real time, so long pauses of the recording don't delay the shutdown.
+    let step = Duration::from_millis(cmp::max((100. * config::get().clock.scale) as u64, 1));
+
+    let mut publishers = HashMap::new();
+    let mut origin = None;
+    let mut count = 0;
+
+    while let Some(entry) = try!(recorder::read_entry(&mut file)) {
+        let kind = match KINDS.iter().find(|kind| kind.tag == entry.tag) {
+            Some(kind) => kind,
+            None => {
+                warn!("skipping an entry of unknown kind {}", entry.tag);
+                continue;
+            }
+        };
+
+        // Keep the original pace, the clock makes it faster or slower (`clock.scale`).
+        let (first_stamp, start) = *origin.get_or_insert((entry.stamp, node::now()));
+        let deadline = nanos(start) + nanos(entry.stamp).saturating_sub(nanos(first_stamp));
+
+        let deadline = Duration::new(deadline / 1000000000, (deadline % 1000000000) as u32);
+
+        if !wait_until(deadline, step) {
+            break;
+        }
+
+        let publisher = publishers.entry((entry.tag, entry.topic.clone()))
+                                  .or_insert_with(|| (kind.publisher)(&entry.topic));
+
+        try!(publisher(&entry.payload));
+        count += 1;
+    }
+
+    info!("played {} messages", count);
+    Ok(())
+}
+
+/// Sleeps until the deadline by steps, returns false if the shutdown is requested meanwhile.
+fn wait_until(deadline: Duration, step: Duration) -> bool {
+    loop {
+        if shutdown::requested() {
+            return false;
+        }
+
+        let now = clock::now();
+
+        if now >= deadline {
+            return true;
+        }
+
+        clock::sleep_until(cmp::min(deadline, now + step));
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1000000000 + duration.subsec_nanos() as u64
+}
@@ -0,0 +1,215 @@
+use std::cmp;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use base::config;
+use base::node::{self, Dropped, Message, Output, Payload, Policy};
+use base::shutdown;
+use base::wire::{self, Wire};
+use messages::{Attitude, SysInfo, VideoFrame};
+
+
+pub const MAGIC: &'static [u8; 8] = b"HODOKREC";
+pub const VERSION: u8 = 2;
+
+/// Recordable type: `record` forwards the topic to the recorder, `publisher` plays it back.
+pub struct Kind {
+    pub tag: u8,
+    pub name: &'static str,
+    pub record: fn(u8, &str, usize, SyncSender<Vec<u8>>) -> Dropped,
+    pub publisher: fn(&str) -> Box<Fn(&[u8]) -> io::Result<()>>
+}
+
+pub static KINDS: &'static [Kind] = &[
+    Kind { tag: 1, name: "attitude", record: record::<Attitude>, publisher: publisher::<Attitude> },
+    Kind { tag: 2, name: "sysinfo", record: record::<SysInfo>, publisher: publisher::<SysInfo> },
+    Kind { tag: 3, name: "video", record: record::<VideoFrame>, publisher: publisher::<VideoFrame> }
+];
+
+fn record<T: Wire + Payload>(tag: u8, topic: &str, queue: usize, tx: SyncSender<Vec<u8>>)
+    -> Dropped
+{
+    let (input, dropped) = node::subscribe_with::<T>(topic, Policy::DropOldest(queue));
+    let topic = topic.to_string();
+
+    thread::spawn(move || {
+        for message in input.iter() {
+            if tx.send(encode(tag, &topic, &message)).is_err() {
+                break;
+            }
+        }
+    });
+
+    dropped
+}
+
+fn publisher<T: Wire + Payload>(topic: &str) -> Box<Fn(&[u8]) -> io::Result<()>> {
+    let output: Output<T> = node::advertise_as(topic);
+    Box::new(move |payload| Ok(output.send(try!(wire::decode::<T>(payload)))))
+}
+
+/// One entry of the recording.
+pub struct Entry {
+    pub tag: u8,
+    pub topic: String,
+    pub stamp: Duration,
+    pub seq: u32,
+    pub source: String,
+    pub payload: Vec<u8>
+}
+
+// Layout: tag (u8), topic (u8 + bytes), stamp (u64, μs), seq (u32),
+//         source (u8 + bytes), payload (u32 + bytes). All numbers are little-endian.
+// The payload is prefixed by the version of the schema, see `base::wire`.
+fn encode<T: Wire>(tag: u8, topic: &str, message: &Message<T>) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(64);
+    let stamp = message.stamp.as_secs() * 1000000 + message.stamp.subsec_nanos() as u64 / 1000;
+
+    buf.write_u8(tag).unwrap();
+    write_str(&mut buf, topic);
+    buf.write_u64::<LittleEndian>(stamp).unwrap();
+    buf.write_u32::<LittleEndian>(message.seq).unwrap();
+    write_str(&mut buf, &message.source);
+
+    let start = buf.len();
+    buf.write_u32::<LittleEndian>(0).unwrap();
+    wire::encode(&message.data, &mut buf);
+
+    let len = (buf.len() - start - 4) as u32;
+    (&mut buf[start..start + 4]).write_u32::<LittleEndian>(len).unwrap();
+    buf
+}
+
+fn write_str(buf: &mut Vec<u8>, string: &str) {
+    let bytes = &string.as_bytes()[..cmp::min(string.len(), 255)];
+    buf.write_u8(bytes.len() as u8).unwrap();
+    buf.extend(bytes);
+}
+
+/// Reads the next entry, returns `None` at the end of the file.
+pub fn read_entry<R: Read>(input: &mut R) -> io::Result<Option<Entry>> {
+    let tag = match input.read_u8() {
+        Ok(tag) => tag,
+        Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err)
+    };
+
+    let topic = try!(read_str(input));
+    let stamp = try!(input.read_u64::<LittleEndian>());
+    let seq = try!(input.read_u32::<LittleEndian>());
+    let source = try!(read_str(input));
+    let len = try!(input.read_u32::<LittleEndian>());
+
+    let mut payload = vec![0; len as usize];
+    try!(input.read_exact(&mut payload));
+
+    Ok(Some(Entry {
+        tag: tag,
+        topic: topic,
+        stamp: Duration::new(stamp / 1000000, (stamp % 1000000) as u32 * 1000),
+        seq: seq,
+        source: source,
+        payload: payload
+    }))
+}
+
+fn read_str<R: Read>(input: &mut R) -> io::Result<String> {
+    let mut buf = vec![0; try!(input.read_u8()) as usize];
+    try!(input.read_exact(&mut buf));
+    String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn read_header<R: Read>(input: &mut R) -> io::Result<()> {
+    let mut magic = [0; 8];
+    try!(input.read_exact(&mut magic));
+
+    if &magic != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a recording"));
+    }
+
+    match try!(input.read_u8()) {
+        VERSION => Ok(()),
+        version => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("unsupported version {}", version)))
+    }
+}
+
+pub fn worker() {
+    let config = &config::get().recorder;
+    let shutdown_rx = shutdown::watch();
+
+    // Recorders block while the file is written, so a slow disk drops the oldest messages of
+    // subscriptions instead of growing the memory.
+    let (tx, rx) = mpsc::sync_channel(0);
+    let default = vec![String::new()];
+    let mut dropped = Vec::new();
+
+    for kind in KINDS.iter().filter(|kind| config.kinds.iter().any(|x| x == kind.name)) {
+        for topic in config.topics.get(kind.name).unwrap_or(&default) {
+            dropped.push((kind.record)(kind.tag, topic, config.queue, tx.clone()));
+        }
+    }
+
+    for name in config.topics.keys().filter(|name| !config.kinds.contains(name)) {
+        warn!("topics of {} are ignored, it isn't recorded", name);
+    }
+
+    let (file, path) = create(&config.path).unwrap();
+    let mut file = BufWriter::new(file);
+    file.write_all(MAGIC).unwrap();
+    file.write_u8(VERSION).unwrap();
+
+    info!("recording {} to {}", config.kinds.join(", "), path.display());
+
+    let mut count = 0;
+
+    loop {
+        select! {
+            entry = rx.recv() => {
+                file.write_all(&entry.unwrap()).unwrap();
+                count += 1;
+            },
+            _ = shutdown_rx.recv() => break
+        }
+    }
+
+    file.flush().unwrap();
+
+    let dropped = dropped.iter().map(|dropped| dropped.count()).fold(0, |sum, x| sum + x);
+    info!("recorded {} messages, dropped {}", count, dropped);
+}
+
+/// Creates a new file named by the start time, e.g. `hodok-1445000000.rec` for `hodok.rec`, so
+/// restarts of the node don't overwrite previous recordings.
+fn create(path: &str) -> io::Result<(File, PathBuf)> {
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("hodok");
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    for attempt in 0.. {
+        let mut name = match attempt {
+            0 => format!("{}-{}", stem, time),
+            _ => format!("{}-{}-{}", stem, time, attempt)
+        };
+
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            name = format!("{}.{}", name, extension);
+        }
+
+        let path = path.with_file_name(name);
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err)
+        }
+    }
+
+    unreachable!();
+}
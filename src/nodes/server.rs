@@ -1,21 +1,34 @@
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::mem;
 use std::net::{TcpListener, TcpStream};
 use std::path::Path;
 use std::str;
-use std::sync::mpsc;
-use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-use byteorder::{BigEndian, WriteBytesExt};
+use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
 use httparse;
 use rustc_serialize::base64::{self, ToBase64};
+use rustc_serialize::json::{Json, ToJson};
 use sha1::Sha1;
 
+use base::{Error, Result};
+use base::config;
+use base::config::Value;
 use base::node;
-use constants::PORT;
-use messages::{Attitude, VideoFrame, SysInfo};
+use base::node::{Limit, Message, Policy, TopicInfo};
+use base::params::{self, ParamInfo};
+use base::shutdown;
+use base::supervisor;
+use base::watchdog;
+use base::wire::{self, Wire, invalid};
+use messages::{Attitude, VideoFrame, SysInfo, NodeState, TopicHealth, LogEntry, Flip};
 
 
 fn get_mime(ext: &str) -> &'static str {
@@ -31,53 +44,99 @@ fn get_mime(ext: &str) -> &'static str {
 struct Handler {
     video: Option<TcpStream>,
     attitude: Option<TcpStream>,
-    sysinfo: Option<TcpStream>
+    sysinfo: Option<TcpStream>,
+    nodes: Option<TcpStream>,
+    health: Option<TcpStream>,
+    log: Option<TcpStream>,
+    node_states: HashMap<String, Arc<Message<NodeState>>>,
+    topic_health: HashMap<String, Arc<Message<TopicHealth>>>,
+    /// Recent records sent to new clients before the live ones.
+    log_history: VecDeque<Arc<Message<LogEntry>>>
 }
 
 impl Handler {
+    // Clients can disconnect or send garbage at any time, it mustn't stop the node.
     fn handle(&mut self, mut stream: TcpStream) {
-        let (path, headers) = self.parse_req(&mut stream);
+        let req = match self.parse_req(&mut stream) {
+            Ok(req) => req,
+            Err(err) => {
+                debug!("skipping a request: {}", err);
+                return;
+            }
+        };
 
-        debug!("handling request {}", path);
+        debug!("handling request {} {}", req.method, req.path);
 
-        if headers.contains_key("Upgrade") {
-            self.handle_ws(stream, &path[1..], &headers.get("Sec-WebSocket-Key").unwrap());
+        let result = if req.headers.contains_key("upgrade") {
+            match req.headers.get("sec-websocket-key") {
+                Some(key) => self.handle_ws(stream, &req.path[1..], key),
+                None => self.send_404(stream)
+            }
+        } else if req.method == "PUT" {
+            self.handle_put(stream, &req.path[1..], &req.body)
+        } else if req.method == "POST" {
+            self.handle_post(stream, &req.path[1..])
         } else {
-            self.handle_http(stream, &path[1..]);
+            self.handle_http(stream, &req.path[1..])
+        };
+
+        if let Err(err) = result {
+            debug!("can't answer {} {}: {}", req.method, req.path, err);
         }
     }
 
-    fn parse_req(&self, stream: &mut TcpStream) -> (String, HashMap<String, String>) {
-        let mut data = [0; 4096];
-        stream.read(&mut data).unwrap();
+    fn parse_req(&self, stream: &mut TcpStream) -> io::Result<Request> {
+        let mut data = vec![0; 4096];
+        let len = try!(stream.read(&mut data));
+        data.truncate(len);
 
         let mut headers_buf = [httparse::EMPTY_HEADER; 16];
         let mut req = httparse::Request::new(&mut headers_buf);
-        req.parse(&data).unwrap().unwrap();
 
+        let header_len = match req.parse(&data) {
+            Ok(httparse::Status::Complete(len)) => len,
+            Ok(httparse::Status::Partial) => return Err(invalid("incomplete headers".to_string())),
+            Err(err) => return Err(invalid(format!("{:?}", err)))
+        };
+
+        let method = req.method.unwrap_or("").to_string();
         let path = req.path.unwrap_or("").to_string();
         let mut headers = HashMap::new();
 
         for header in req.headers {
-            let name = header.name.to_string();
-            let value = str::from_utf8(header.value).unwrap().to_string();
+            let name = header.name.to_lowercase();
+            let value = String::from_utf8_lossy(header.value).into_owned();
             headers.insert(name, value);
         }
 
-        (path, headers)
+        // The body can come in separate packets.
+        let body_len = headers.get("content-length").and_then(|x| x.parse().ok()).unwrap_or(0);
+        let mut body = data[header_len..].to_vec();
+
+        if body.len() < body_len {
+            let rest = body_len - body.len();
+            try!(stream.take(rest as u64).read_to_end(&mut body));
+        }
+
+        Ok(Request {
+            method: method,
+            path: path,
+            headers: headers,
+            body: body
+        })
     }
 
-    fn handle_http(&self, mut stream: TcpStream, mut path: &str) {
-        if path == "" {
-            path = "index.html";
+    fn handle_http(&self, mut stream: TcpStream, mut path: &str) -> io::Result<()> {
+        match path {
+            "" => path = "index.html",
+            "topics" => return self.send_json(stream, node::topics().to_json()),
+            "params" => return self.send_json(stream, params::list().to_json()),
+            _ => {}
         }
 
         let mut file = match File::open(path) {
             Ok(f) => f,
-            Err(_) => {
-                self.send_404(stream);
-                return;
-            }
+            Err(_) => return self.send_404(stream)
         };
 
         let ext = Path::new(path).extension().and_then(|x| x.to_str()).unwrap_or("");
@@ -88,33 +147,112 @@ impl Handler {
         header.push_str("\r\n\r\n");
 
         let mut data = Vec::new();
-        file.read_to_end(&mut data).unwrap();
+        try!(file.read_to_end(&mut data));
 
-        stream.write_all(header.as_bytes()).unwrap();
-        stream.write_all(&data).unwrap();
+        try!(stream.write_all(header.as_bytes()));
+        try!(stream.write_all(&data));
 
-        stream.write(b"\r\n\r\n").unwrap();
+        stream.write_all(b"\r\n\r\n")
     }
 
-    fn send_404(&self, mut stream: TcpStream) {
-        stream.write(b"HTTP/1.1 404 Not Found\r\n").unwrap();
+    // `PUT /params/<name>` with a JSON value as the body.
+    fn handle_put(&self, stream: TcpStream, path: &str, body: &[u8]) -> io::Result<()> {
+        if !path.starts_with("params/") {
+            return self.send_404(stream);
+        }
+
+        let name = &path["params/".len()..];
+
+        let result = str::from_utf8(body).ok()
+            .and_then(|body| Json::from_str(body.trim()).ok())
+            .and_then(|json| json_to_value(&json))
+            .ok_or_else(|| "expected a number or a boolean".into())
+            .and_then(|value| params::set(name, &value));
+
+        match result {
+            Ok(_) => {
+                let info = params::list().into_iter().find(|param| param.name == name);
+                self.send_json(stream, info.to_json())
+            },
+            Err(err) => self.send_error(stream, &err)
+        }
     }
 
-    fn handle_ws(&mut self, mut stream: TcpStream, channel: &str, key: &str) {
-        stream.write(b"HTTP/1.1 101 Switching Protocols\r\n\
-                       Upgrade: websocket\r\n\
-                       Connection: Upgrade\r\n\
-                       Sec-WebSocket-Accept: ").unwrap();
+    // `POST /video/flip/<horizontal|vertical>`, answers the new flip.
+    fn handle_post(&self, stream: TcpStream, path: &str) -> io::Result<()> {
+        let flip = match path {
+            "video/flip/horizontal" => Flip::Horizontal,
+            "video/flip/vertical" => Flip::Vertical,
+            _ => return self.send_404(stream)
+        };
+
+        let result = node::call::<Flip, Result<bool>>("video.flip", flip, Duration::from_secs(1))
+            .and_then(|result| result);
 
-        stream.write(self.compute_accept(key).as_bytes()).unwrap();
-        stream.write(b"\r\n\r\n").unwrap();
+        match result {
+            Ok(flip) => self.send_json(stream, flip.to_json()),
+            Err(err) => self.send_error(stream, &err)
+        }
+    }
+
+    fn send_json(&self, mut stream: TcpStream, json: Json) -> io::Result<()> {
+        try!(stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n"));
+        stream.write_all(json.to_string().as_bytes())
+    }
+
+    fn send_error(&self, mut stream: TcpStream, err: &Error) -> io::Result<()> {
+        try!(stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain\r\n\r\n"));
+        stream.write_all(err.to_string().as_bytes())
+    }
+
+    fn send_404(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.write_all(b"HTTP/1.1 404 Not Found\r\n")
+    }
+
+    fn handle_ws(&mut self, mut stream: TcpStream, channel: &str, key: &str) -> io::Result<()> {
+        try!(stream.write_all(b"HTTP/1.1 101 Switching Protocols\r\n\
+                                Upgrade: websocket\r\n\
+                                Connection: Upgrade\r\n\
+                                Sec-WebSocket-Accept: "));
+
+        try!(stream.write_all(self.compute_accept(key).as_bytes()));
+        try!(stream.write_all(b"\r\n\r\n"));
 
         match channel {
             "video" => self.video = Some(stream),
             "attitude" => self.attitude = Some(stream),
             "sysinfo" => self.sysinfo = Some(stream),
+            "nodes" => {
+                self.nodes = Some(stream);
+
+                let states = self.node_states.values().cloned().collect::<Vec<_>>();
+
+                for state in states {
+                    self.send_node_state(&state);
+                }
+            },
+            "health" => {
+                self.health = Some(stream);
+
+                let states = self.topic_health.values().cloned().collect::<Vec<_>>();
+
+                for state in states {
+                    self.send_topic_health(&state);
+                }
+            },
+            "log" => {
+                self.log = Some(stream);
+
+                let entries = self.log_history.iter().cloned().collect::<Vec<_>>();
+
+                for entry in entries {
+                    self.send_log_entry(&entry);
+                }
+            },
             _ => {}
         }
+
+        Ok(())
     }
 
     fn compute_accept(&self, key: &str) -> String {
@@ -132,87 +270,287 @@ impl Handler {
         out.to_base64(base64::STANDARD)
     }
 
-    fn send_video_frame(&mut self, frame: &VideoFrame) {
-        let mut stream = self.video.take();
+    fn send_video_frame(&mut self, frame: &Message<VideoFrame>) {
+        let stream = self.video.take();
+        self.video = stream.and_then(|ws| self.send_ws(ws, frame));
+    }
 
-        if let Some(ref mut ws) = stream {
-            self.send_ws(ws, &frame[..]);
-        }
+    fn send_attitude(&mut self, attitude: &Message<Attitude>) {
+        let stream = self.attitude.take();
+        self.attitude = stream.and_then(|ws| self.send_ws(ws, attitude));
+    }
 
-        self.video = stream;
+    fn send_sysinfo(&mut self, sysinfo: &Message<SysInfo>) {
+        let stream = self.sysinfo.take();
+        self.sysinfo = stream.and_then(|ws| self.send_ws(ws, sysinfo));
     }
 
-    fn send_attitude(&mut self, attitude: &Attitude) {
-        let mut stream = self.attitude.take();
+    fn update_node_state(&mut self, state: Arc<Message<NodeState>>) {
+        self.send_node_state(&state);
+        self.node_states.insert(state.name.clone(), state);
+    }
 
-        if let Some(ref mut ws) = stream {
-            let data: &[u8; 32] = unsafe { mem::transmute(attitude) };
-            self.send_ws(ws, data);
+    fn send_node_state(&mut self, state: &Message<NodeState>) {
+        let stream = self.nodes.take();
+        self.nodes = stream.and_then(|ws| self.send_ws(ws, state));
+    }
+
+    fn update_topic_health(&mut self, health: Arc<Message<TopicHealth>>) {
+        self.send_topic_health(&health);
+        self.topic_health.insert(health.topic.clone(), health);
+    }
+
+    fn send_topic_health(&mut self, health: &Message<TopicHealth>) {
+        let stream = self.health.take();
+        self.health = stream.and_then(|ws| self.send_ws(ws, health));
+    }
+
+    fn update_log(&mut self, entry: Arc<Message<LogEntry>>) {
+        self.send_log_entry(&entry);
+
+        if self.log_history.len() == config::get().server.log_history {
+            self.log_history.pop_front();
         }
 
-        self.attitude = stream;
+        self.log_history.push_back(entry);
     }
 
-    fn send_sysinfo(&mut self, sysinfo: &SysInfo) {
-        let mut stream = self.sysinfo.take();
+    fn send_log_entry(&mut self, entry: &Message<LogEntry>) {
+        let stream = self.log.take();
+        self.log = stream.and_then(|ws| self.send_ws(ws, entry));
+    }
+
+    fn close(&mut self) {
+        let streams = vec![self.video.take(), self.attitude.take(), self.sysinfo.take(),
+                           self.nodes.take(), self.health.take(), self.log.take()];
 
-        if let Some(ref mut ws) = stream {
-            let data: &[u8; 7] = unsafe { mem::transmute(sysinfo) };
-            self.send_ws(ws, data);
+        for mut ws in streams.into_iter().filter_map(|x| x) {
+            // Fin: 1, rsv: 0, opcode: 0x8 (close), mask: 0, no payload.
+            let _ = ws.write_all(&[0b1_000_1000, 0]);
         }
+    }
 
-        self.sysinfo = stream;
+    /// Sends the message to the client, returns the stream back unless the client is gone.
+    fn send_ws<T: Wire>(&self, mut stream: TcpStream, message: &Message<T>) -> Option<TcpStream> {
+        match self.write_ws(&mut stream, message) {
+            Ok(_) => Some(stream),
+            Err(err) => {
+                debug!("closing the websocket: {}", err);
+                None
+            }
+        }
     }
 
-    fn send_ws(&self, stream: &mut TcpStream, data: &[u8]) {
+    fn write_ws<T: Wire>(&self, stream: &mut TcpStream, message: &Message<T>) -> io::Result<()> {
+        // The envelope: stamp (f64, seconds), seq (u32), source's length (u8) and source.
+        // Then the data prefixed by the version of the schema, see `base::wire`.
+        let source = &message.source.as_bytes()[..cmp::min(message.source.len(), 255)];
+        let stamp = message.stamp.as_secs() as f64 + message.stamp.subsec_nanos() as f64 * 1e-9;
+
+        let mut data = Vec::with_capacity(64 + source.len());
+        data.write_f64::<LittleEndian>(stamp).unwrap();
+        data.write_u32::<LittleEndian>(message.seq).unwrap();
+        data.write_u8(source.len() as u8).unwrap();
+        data.extend(source);
+        wire::encode(&message.data, &mut data);
+
         let len = data.len();
 
         // Fin: 1, rsv: 0, opcode: 0x2 (binary).
-        stream.write_u8(0b1_000_0010).unwrap();
+        try!(stream.write_u8(0b1_000_0010));
 
         // Mask: 0.
         match len {
-            0...125 => stream.write_u8(len as u8).unwrap(),
+            0...125 => try!(stream.write_u8(len as u8)),
             126...65535 => {
-                stream.write_u8(126).unwrap();
-                stream.write_u16::<BigEndian>(len as u16).unwrap();
+                try!(stream.write_u8(126));
+                try!(stream.write_u16::<BigEndian>(len as u16));
             },
             _ => {
-                stream.write_u8(127).unwrap();
-                stream.write_u64::<BigEndian>(len as u64).unwrap();
+                try!(stream.write_u8(127));
+                try!(stream.write_u64::<BigEndian>(len as u64));
             }
         }
 
-        stream.write(data).unwrap();
-        stream.flush().unwrap();
+        try!(stream.write_all(&data));
+        stream.flush()
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    /// Names are lowercased, because they're case-insensitive.
+    headers: HashMap<String, String>,
+    body: Vec<u8>
+}
+
+impl ToJson for ParamInfo {
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+
+        object.insert("name".to_string(), self.name.to_json());
+        object.insert("value".to_string(), value_to_json(&self.value));
+        object.insert("default".to_string(), value_to_json(&self.default));
+        object.insert("range".to_string(), match self.range {
+            Some((ref min, ref max)) => Json::Array(vec![value_to_json(min), value_to_json(max)]),
+            None => Json::Null
+        });
+        Json::Object(object)
+    }
+}
+
+fn value_to_json(value: &Value) -> Json {
+    match *value {
+        Value::Str(ref string) => string.to_json(),
+        Value::Num(num) => num.to_json(),
+        Value::Bool(flag) => flag.to_json(),
+        Value::Array(ref array) => Json::Array(array.iter().map(value_to_json).collect())
+    }
+}
+
+fn json_to_value(json: &Json) -> Option<Value> {
+    match *json {
+        Json::I64(num) => Some(Value::Num(num as f64)),
+        Json::U64(num) => Some(Value::Num(num as f64)),
+        Json::F64(num) => Some(Value::Num(num)),
+        Json::Boolean(flag) => Some(Value::Bool(flag)),
+        _ => None
+    }
+}
+
+impl ToJson for TopicInfo {
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+
+        let subscribers = self.subscribers.iter().map(|subscriber| {
+            let mut object = BTreeMap::new();
+            object.insert("node".to_string(), subscriber.node.to_json());
+            object.insert("policy".to_string(), format!("{:?}", subscriber.policy).to_json());
+            object.insert("limit".to_string(), format!("{:?}", subscriber.limit).to_json());
+            object.insert("queued".to_string(), subscriber.queued.to_json());
+            object.insert("dropped".to_string(), subscriber.dropped.to_json());
+            Json::Object(object)
+        }).collect();
+
+        object.insert("type".to_string(), self.type_name.to_json());
+        object.insert("topic".to_string(), self.topic.to_json());
+        object.insert("publishers".to_string(),
+                      Json::Array(self.publishers.iter().map(|name| name.to_json()).collect()));
+        object.insert("subscribers".to_string(), Json::Array(subscribers));
+        object.insert("total".to_string(), self.total.to_json());
+        object.insert("rate".to_string(), self.rate.to_json());
+        object.insert("bandwidth".to_string(), self.bandwidth.to_json());
+        object.insert("expected_rate".to_string(), self.expected_rate.to_json());
+        object.insert("silence".to_string(),
+                      (self.silence.as_secs() as f64 + self.silence.subsec_nanos() as f64 * 1e-9)
+                          .to_json());
+        Json::Object(object)
     }
 }
 
 pub fn worker() {
-    let video_frame_rx = node::subscribe::<VideoFrame>();
-    let attitude_rx = node::subscribe::<Attitude>();
-    let sys_info_rx = node::subscribe::<SysInfo>();
+    let config = &config::get().server;
+
+    let (video_frame_rx, video_dropped) =
+        node::subscribe_with::<VideoFrame>("", Policy::DropOldest(config.video_queue));
+    let (attitude_rx, _) = node::subscribe_limited::<Attitude>("", Policy::KeepLatest,
+                                                              Limit::MaxRate(config.attitude_rate));
+    let (sys_info_rx, _) = node::subscribe_with::<SysInfo>("", Policy::KeepLatest);
+
+    let node_state_rx = node::subscribe::<NodeState>();
+    let health_rx = node::subscribe::<TopicHealth>();
+    let (log_rx, _) = node::subscribe_with::<LogEntry>("", Policy::DropOldest(config.log_history));
+    let shutdown_rx = shutdown::watch();
+
+    let mut hander = Handler {
+        video: None,
+        attitude: None,
+        sysinfo: None,
+        nodes: None,
+        health: None,
+        log: None,
+        node_states: HashMap::new(),
+        topic_health: HashMap::new(),
+        log_history: VecDeque::with_capacity(config.log_history)
+    };
+
+    for state in supervisor::states() {
+        hander.update_node_state(Arc::new(state));
+    }
+
+    for health in watchdog::states() {
+        hander.update_topic_health(Arc::new(health));
+    }
+
+    let addr = ("0.0.0.0", config.port);
+    let listener = TcpListener::bind(addr).unwrap();
 
-    let mut hander = Handler { video: None, attitude: None, sysinfo: None };
+    info!("listening on {}:{}", addr.0, addr.1);
 
     let (tcp_tx, tcp_rx) = mpsc::channel();
-    thread::spawn(move || {
-        let addr = ("0.0.0.0", PORT);
-        let listener = TcpListener::bind(addr).unwrap();
+    let stop = Arc::new(AtomicBool::new(false));
+    let acceptor_stop = stop.clone();
 
-        info!("listening on {}:{}", addr.0, addr.1);
+    let _acceptor = Acceptor {
+        port: config.port,
+        stop: stop,
+        thread: Some(thread::spawn(move || accept(listener, tcp_tx, acceptor_stop)))
+    };
 
-        for stream in listener.incoming() {
-            tcp_tx.send(stream.unwrap()).unwrap();
-        }
-    });
+    let mut reported_drops = 0;
 
     loop {
         select! {
-            stream = tcp_rx.recv() => hander.handle(stream.unwrap()),
-            frame = video_frame_rx.recv() => hander.send_video_frame(&*frame.unwrap()),
-            attitude = attitude_rx.recv() => hander.send_attitude(&*attitude.unwrap()),
-            sysinfo = sys_info_rx.recv() => hander.send_sysinfo(&*sysinfo.unwrap())
+            stream = tcp_rx.recv() => match stream.unwrap() {
+                Ok(stream) => hander.handle(stream),
+                Err(err) => warn!("can't accept a connection: {}", err)
+            },
+            frame = video_frame_rx.recv() => {
+                hander.send_video_frame(&frame.unwrap());
+
+                let drops = video_dropped.count();
+                if drops > reported_drops {
+                    warn!("dropped {} video frames", drops - reported_drops);
+                    reported_drops = drops;
+                }
+            },
+            attitude = attitude_rx.recv() => hander.send_attitude(&attitude.unwrap()),
+            sysinfo = sys_info_rx.recv() => hander.send_sysinfo(&sysinfo.unwrap()),
+            state = node_state_rx.recv() => hander.update_node_state(state.unwrap()),
+            health = health_rx.recv() => hander.update_topic_health(health.unwrap()),
+            entry = log_rx.recv() => hander.update_log(entry.unwrap()),
+            _ = shutdown_rx.recv() => break
+        }
+    }
+
+    hander.close();
+}
+
+// `accept` blocks, so the thread notices the stop only after the next connection.
+fn accept(listener: TcpListener, tcp_tx: Sender<io::Result<TcpStream>>, stop: Arc<AtomicBool>) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) || tcp_tx.send(stream).is_err() {
+            break;
+        }
+    }
+}
+
+/// Wakes and joins the accepting thread when the worker stops or panics, so the port is free for
+/// the restarted worker.
+struct Acceptor {
+    port: u16,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl Drop for Acceptor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
         }
     }
 }
@@ -1,8 +1,8 @@
 use std::fs::File;
 use std::io::Read;
 
+use base::config;
 use base::node;
-use constants::SYSINFO_RATE;
 use messages::SysInfo;
 
 
@@ -88,9 +88,12 @@ pub fn worker() {
 
     let mut informer = SysInformer { total_mem: 0, prev_idle: 0, prev_total: 0 };
 
-    info!("running at {}Hz", SYSINFO_RATE);
+    let rate = config::get().sysinfo.rate;
+    sys_info_tx.expect_rate(rate);
 
-    for _ in node::periodic(SYSINFO_RATE) {
+    info!("running at {}Hz", rate);
+
+    for _ in node::periodic(rate) {
         let (free_mem, avail_mem) = informer.get_mem();
         let cpu = informer.get_cpu();
         let loadavg = informer.get_loadavg();
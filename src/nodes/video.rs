@@ -1,37 +1,91 @@
 use std::default::Default;
+use std::time::Duration;
 
 use rscam::{Camera, Config};
 use rscam::{CID_MPEG_VIDEO_H264_PROFILE, MPEG_VIDEO_H264_PROFILE_BASELINE};
 use rscam::{CID_MPEG_VIDEO_H264_I_PERIOD, CID_MPEG_VIDEO_REPEAT_SEQ_HEADER, CID_HFLIP, CID_VFLIP};
 
+use base::Result;
+use base::clock;
+use base::config;
+use base::config::Value;
 use base::node;
-use constants::{VIDEO_DEVICE, VIDEO_FPS, VIDEO_RESOLUTION, VIDEO_GOF_SIZE};
-use messages::VideoFrame;
+use base::params;
+use base::shutdown;
+use messages::{Flip, VideoFrame};
 
 
 pub fn worker() {
     let video = node::advertise::<VideoFrame>();
 
-    let mut camera = Camera::new(VIDEO_DEVICE).unwrap();
+    let config = &config::get().video;
+
+    let (mut hflip, hflip_rx) = params::declare_flag("video.hflip", config.hflip);
+    let (mut vflip, vflip_rx) = params::declare_flag("video.vflip", config.vflip);
+
+    let flip_service = node::serve::<Flip, Result<bool>>("video.flip");
+
+    let mut camera = Camera::new(&config.device).unwrap();
 
     camera.set_control(CID_MPEG_VIDEO_H264_PROFILE, MPEG_VIDEO_H264_PROFILE_BASELINE).unwrap();
-    camera.set_control(CID_MPEG_VIDEO_H264_I_PERIOD, VIDEO_GOF_SIZE).unwrap();
+    camera.set_control(CID_MPEG_VIDEO_H264_I_PERIOD, config.gof_size).unwrap();
     camera.set_control(CID_MPEG_VIDEO_REPEAT_SEQ_HEADER, true).unwrap();
-    camera.set_control(CID_HFLIP, true).unwrap();
-    camera.set_control(CID_VFLIP, true).unwrap();
+    camera.set_control(CID_HFLIP, hflip).unwrap();
+    camera.set_control(CID_VFLIP, vflip).unwrap();
 
     camera.start(&Config {
-        interval: (1, VIDEO_FPS),
-        resolution: VIDEO_RESOLUTION,
+        interval: (1, config.fps),
+        resolution: config.resolution,
         format: b"H264",
         ..Default::default()
     }).unwrap();
 
-    info!("stream: {}x{}, {}fps, GoF = {}", VIDEO_RESOLUTION.0, VIDEO_RESOLUTION.1,
-                                            VIDEO_FPS, VIDEO_GOF_SIZE);
+    video.expect_rate(config.fps as f32);
+
+    info!("stream: {}x{}, {}fps, GoF = {}", config.resolution.0, config.resolution.1,
+                                            config.fps, config.gof_size);
+
+    while !shutdown::requested() {
+        // Toggle through parameters, so the flip is saved and applied as any other change.
+        while let Ok(request) = flip_service.try_recv() {
+            let (name, current) = match *request {
+                Flip::Horizontal => ("video.hflip", &mut hflip),
+                Flip::Vertical => ("video.vflip", &mut vflip)
+            };
+
+            // Update at once, the next request in the same drain toggles the new value.
+            let result = params::set(name, &Value::Bool(!*current));
+
+            if result.is_ok() {
+                *current = !*current;
+            }
+
+            request.reply(result.map(|_| *current));
+        }
+
+        if let Some(flip) = params::latest(&hflip_rx) {
+            hflip = flip;
+
+            if let Err(err) = camera.set_control(CID_HFLIP, flip) {
+                warn!("cannot set the horizontal flip: {}", err);
+            }
+        }
+
+        if let Some(flip) = params::latest(&vflip_rx) {
+            vflip = flip;
+
+            if let Err(err) = camera.set_control(CID_VFLIP, flip) {
+                warn!("cannot set the vertical flip: {}", err);
+            }
+        }
 
-    loop {
         let frame = camera.capture().unwrap();
-        video.send(frame);
+
+        // V4L2 uses `CLOCK_MONOTONIC` for timestamps.
+        let stamp = frame.get_timestamp();
+        let stamp = clock::from_monotonic(Duration::new(stamp / 1000000,
+                                                        (stamp % 1000000) as u32 * 1000));
+
+        video.send_at(VideoFrame(frame[..].to_vec()), stamp);
     }
 }
@@ -3,19 +3,34 @@ import {EventEmitter} from 'events';
 
 let channels = Object.create(null);
 
+// `version` is the version of the schema of messages, see `base::wire`.
 export default class Channel extends EventEmitter {
-    constructor(name) {
+    constructor(name, version) {
         if (name in channels)
             return channels[name];
 
         super();
         channels[name] = this;
 
+        this.version = version;
+
+        // `EventEmitter` throws errors without listeners, report them instead.
+        this.on('error', err => console.warn(err));
+
         let socket = this.socket = new WebSocket(`ws://${location.host}/${name}`);
         socket.binaryType = 'arraybuffer';
 
         socket.onopen = () => this.emit('connect');
-        socket.onmessage = e => this.emit('data', e.data);
+        socket.onmessage = e => {
+            let {payload, meta} = parseMessage(e.data);
+
+            if (meta.version !== this.version) {
+                this.emit('error', new Error(`${name}: unsupported schema version ${meta.version}`));
+                return;
+            }
+
+            this.emit('data', payload, meta);
+        };
 
         socket.onclose = e => {
             if (!e.wasClean)
@@ -30,3 +45,20 @@ export default class Channel extends EventEmitter {
         this.socket.close();
     }
 }
+
+// The envelope: stamp (f64, seconds), seq (u32), source's length (u8) and source.
+// Then the version of the schema (u8) and the payload.
+function parseMessage(raw) {
+    let dv = new DataView(raw);
+    let length = dv.getUint8(12);
+
+    return {
+        meta: {
+            stamp: dv.getFloat64(0, true),
+            seq: dv.getUint32(8, true),
+            source: String.fromCharCode.apply(null, new Uint8Array(raw, 13, length)),
+            version: dv.getUint8(13 + length)
+        },
+        payload: raw.slice(14 + length)
+    };
+}